@@ -0,0 +1,119 @@
+use crate::generator::colours::ColourRegistry;
+use crate::json::{read_timetable_json, write_timetable_json, TimetableFile};
+use crate::types::StudentIdentity;
+use std::path::PathBuf;
+
+/// Local copy of everything fetched from the school's website,
+/// so outputs can be regenerated without scraping again.
+///
+/// ```text
+/// cache/
+/// ├── html/         raw timetable pages
+/// ├── timetables/   parsed timetables as JSON
+/// └── colours.json  subject codes in the order they were given colours
+/// ```
+pub(crate) struct Cache {
+    root: PathBuf,
+}
+
+impl Cache {
+    pub(crate) fn open(root: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let cache = Self { root: root.into() };
+
+        std::fs::create_dir_all(cache.html_dir())?;
+        std::fs::create_dir_all(cache.timetables_dir())?;
+
+        Ok(cache)
+    }
+
+    pub(crate) fn write_html(&self, student: &StudentIdentity, html: &str) -> anyhow::Result<()> {
+        let path = self.html_dir().join(format!("{}.html", file_stem(student)));
+        std::fs::write(path, html)?;
+
+        Ok(())
+    }
+
+    pub(crate) fn write_timetable(&self, file: &TimetableFile) -> anyhow::Result<()> {
+        let path = self
+            .timetables_dir()
+            .join(format!("{}.json", file_stem(&file.student)));
+        write_timetable_json(&path, file)
+    }
+
+    /// Reads every cached timetable, sorted by student
+    pub(crate) fn read_timetables(&self) -> anyhow::Result<Vec<TimetableFile>> {
+        let mut files = Vec::new();
+
+        for entry in std::fs::read_dir(self.timetables_dir())? {
+            let path = entry?.path();
+            if path.extension().and_then(|extension| extension.to_str()) != Some("json") {
+                continue;
+            }
+
+            files.push(read_timetable_json(&path)?);
+        }
+
+        files.sort_by(|a, b| a.student.cmp(&b.student));
+
+        Ok(files)
+    }
+
+    /// Reads the colour registry, which is empty before anything has been generated
+    pub(crate) fn read_colour_registry(&self) -> anyhow::Result<ColourRegistry> {
+        let path = self.root.join("colours.json");
+        if !path.exists() {
+            return Ok(ColourRegistry::default());
+        }
+
+        let json = std::fs::read_to_string(&path)?;
+        serde_json::from_str(&json)
+            .map_err(|e| anyhow::anyhow!("failed to parse {}: {}", path.display(), e))
+    }
+
+    pub(crate) fn write_colour_registry(&self, registry: &ColourRegistry) -> anyhow::Result<()> {
+        let json = serde_json::to_string_pretty(registry)?;
+        std::fs::write(self.root.join("colours.json"), json)?;
+
+        Ok(())
+    }
+
+    fn html_dir(&self) -> PathBuf {
+        self.root.join("html")
+    }
+
+    fn timetables_dir(&self) -> PathBuf {
+        self.root.join("timetables")
+    }
+}
+
+pub(crate) fn file_stem(student: &StudentIdentity) -> String {
+    format!(
+        "{}+{}+{}",
+        student.id,
+        student.first.to_lowercase(),
+        student.last.to_lowercase()
+    )
+}
+
+/// Undoes `file_stem`, although names come back capitalised rather than as they were
+pub(crate) fn parse_file_stem(stem: &str) -> Option<StudentIdentity> {
+    let capitalise = |name: &str| {
+        let mut chars = name.chars();
+        chars
+            .next()
+            .map(|first| first.to_uppercase().chain(chars).collect())
+            .unwrap_or_default()
+    };
+
+    let mut parts = stem.splitn(3, '+');
+    let (id, first, last) = (parts.next()?, parts.next()?, parts.next()?);
+    if id.is_empty() || first.is_empty() || last.is_empty() {
+        return None;
+    }
+
+    Some(StudentIdentity {
+        first: capitalise(first),
+        last: capitalise(last),
+        id: id.to_string(),
+    })
+}
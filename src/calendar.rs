@@ -0,0 +1,637 @@
+use crate::generator::bell_times::{self, BellTimes, PeriodTimes};
+use crate::generator::merge::merge_doubles;
+use crate::generator::template::Templates;
+use crate::generator::{day_events, timetable_events, Event, EventOptions};
+use crate::types::{parse_week_name, week_name, DayOfWeek, Period, Timetable};
+use anyhow::Context;
+use chrono::{Datelike, Duration, NaiveDate};
+use serde::{Deserialize, Deserializer};
+use std::path::Path;
+
+/// School terms and the days off within them, read from a TOML file
+///
+/// ```toml
+/// [[terms]]
+/// start = "2023-01-27"
+/// end = "2023-04-06"
+/// # Week of the cycle the term's first full week is, defaults to A
+/// start_week = "A"
+///
+/// [[holidays]]
+/// name = "Anzac Day"
+/// start = "2023-04-25"
+/// # Last day off, defaults to the start for a single day
+/// end = "2023-04-25"
+///
+/// [[pupil_free_days]]
+/// name = "Staff Development Day"
+/// start = "2023-07-17"
+///
+/// [[special_days]]
+/// name = "Athletics Carnival"
+/// start = "2023-05-04"
+/// # Periods shown as the special day instead of the usual lesson, even hidden free periods
+/// replace = ["P1", "P2", "P3", "P4"]
+/// # Periods which don't happen
+/// cancel = ["P5", "P6", "P7", "P8"]
+/// # Bell times for the day in the same format as the bell times file,
+/// # any periods left out don't happen
+/// [[special_days.bell_times]]
+/// period = "P1"
+/// ...
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct TermCalendar {
+    terms: Vec<Term>,
+    #[serde(default)]
+    holidays: Vec<DayOff>,
+    /// Days when staff are at school but students aren't
+    #[serde(default)]
+    pupil_free_days: Vec<DayOff>,
+    /// School days with different bells or periods
+    #[serde(default)]
+    special_days: Vec<SpecialDay>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Term {
+    start: NaiveDate,
+    end: NaiveDate,
+    #[serde(default, deserialize_with = "deserialize_week")]
+    start_week: usize,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DayOff {
+    name: String,
+    start: NaiveDate,
+    end: Option<NaiveDate>,
+}
+
+impl DayOff {
+    fn contains(&self, date: NaiveDate) -> bool {
+        self.start <= date && date <= self.end.unwrap_or(self.start)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SpecialDay {
+    name: String,
+    start: NaiveDate,
+    end: Option<NaiveDate>,
+    bell_times: Option<Vec<PeriodTimes>>,
+    #[serde(default)]
+    cancel: Vec<Period>,
+    #[serde(default)]
+    replace: Vec<Period>,
+}
+
+impl SpecialDay {
+    fn contains(&self, date: NaiveDate) -> bool {
+        self.start <= date && date <= self.end.unwrap_or(self.start)
+    }
+}
+
+/// What happens at school on a particular date
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum DateStatus<'a> {
+    School {
+        /// Index of the week in the cycle, 0 being week A
+        week: usize,
+        day_of_week: DayOfWeek,
+    },
+    Weekend,
+    /// Between terms, or outside the calendar altogether
+    Holidays,
+    Holiday(&'a str),
+    PupilFree(&'a str),
+}
+
+impl std::fmt::Display for DateStatus<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DateStatus::School { week, day_of_week } => {
+                write!(f, "Week {} {:?}", week_name(*week), day_of_week)
+            }
+            DateStatus::Weekend => write!(f, "weekend"),
+            DateStatus::Holidays => write!(f, "school holidays"),
+            DateStatus::Holiday(name) => write!(f, "{name}"),
+            DateStatus::PupilFree(name) => write!(f, "{name} (pupil free)"),
+        }
+    }
+}
+
+/// A lesson on a particular date
+#[derive(Clone, Copy)]
+pub(crate) struct DatedEvent<'a> {
+    pub(crate) date: NaiveDate,
+    pub(crate) event: Event<'a>,
+    /// Name of the special day taking over the lesson
+    pub(crate) replaced_by: Option<&'a str>,
+}
+
+impl<'a> DatedEvent<'a> {
+    pub(crate) fn title(&self, templates: &Templates) -> String {
+        match self.replaced_by {
+            Some(name) => name.to_string(),
+            None => templates.title(&self.event),
+        }
+    }
+
+    pub(crate) fn info(&self, templates: &Templates) -> Option<String> {
+        match self.replaced_by {
+            Some(_) => None,
+            None => templates.info(&self.event),
+        }
+    }
+}
+
+impl TermCalendar {
+    pub(crate) fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("failed to read {}: {}", path.display(), e))?;
+        let calendar: Self = toml::from_str(&text)
+            .map_err(|e| anyhow::anyhow!("failed to parse {}: {}", path.display(), e))?;
+
+        calendar
+            .validate()
+            .map_err(|e| anyhow::anyhow!("invalid calendar in {}: {}", path.display(), e))?;
+
+        Ok(calendar)
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(!self.terms.is_empty(), "no terms");
+
+        for (idx, term) in self.terms.iter().enumerate() {
+            anyhow::ensure!(
+                term.end >= term.start,
+                "term starting {} ends before it starts",
+                term.start
+            );
+            if let Some(next) = self.terms.get(idx + 1) {
+                anyhow::ensure!(
+                    next.start > term.end,
+                    "term starting {} must start after the term ending {}",
+                    next.start,
+                    term.end
+                );
+            }
+        }
+
+        // Days off between terms are implied, so only ones during a term are listed
+        let days = self
+            .holidays
+            .iter()
+            .chain(&self.pupil_free_days)
+            .map(|day| (&day.name, day.start, day.end))
+            .chain(
+                self.special_days
+                    .iter()
+                    .map(|day| (&day.name, day.start, day.end)),
+            );
+        for (name, start, end) in days {
+            let end = end.unwrap_or(start);
+            anyhow::ensure!(end >= start, "{} ends before it starts", name);
+            anyhow::ensure!(
+                self.terms
+                    .iter()
+                    .any(|term| term.start <= start && end <= term.end),
+                "{} isn't during a term",
+                name
+            );
+        }
+
+        for special_day in &self.special_days {
+            if let Some(periods) = &special_day.bell_times {
+                bell_times::Day::from_periods(periods).map_err(|e| {
+                    anyhow::anyhow!("invalid bell times for {}: {}", special_day.name, e)
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// First and last day of school in the calendar
+    pub(crate) fn range(&self) -> (NaiveDate, NaiveDate) {
+        (self.terms[0].start, self.terms.last().unwrap().end)
+    }
+
+    pub(crate) fn status(&self, date: NaiveDate, weeks: usize) -> DateStatus<'_> {
+        let Some(day_of_week) = DayOfWeek::from_weekday(date.weekday()) else {
+            return DateStatus::Weekend;
+        };
+
+        if !self
+            .terms
+            .iter()
+            .any(|term| term.start <= date && date <= term.end)
+        {
+            return DateStatus::Holidays;
+        }
+        if let Some(holiday) = self.holidays.iter().find(|day| day.contains(date)) {
+            return DateStatus::Holiday(&holiday.name);
+        }
+        if let Some(day) = self.pupil_free_days.iter().find(|day| day.contains(date)) {
+            return DateStatus::PupilFree(&day.name);
+        }
+
+        DateStatus::School {
+            week: self.cycle_week(date, weeks),
+            day_of_week,
+        }
+    }
+
+    /// Every lesson from `from` to `to` inclusive, in order, skipping days without school
+    /// and following the bell times of special days
+    pub(crate) fn dated_events<'a>(
+        &'a self,
+        t: &'a Timetable,
+        bell_times: &BellTimes,
+        options: &'a EventOptions,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> anyhow::Result<Vec<DatedEvent<'a>>> {
+        // Make sure the usual bell times work for every lesson,
+        // even if a special day means some of them don't happen
+        timetable_events(t, bell_times, options)?;
+        if t.weeks.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut dated_events = Vec::new();
+        let mut date = from;
+        while date <= to {
+            if let DateStatus::School { week, day_of_week } = self.status(date, t.weeks.len()) {
+                let events = self
+                    .date_events(t, bell_times, options, date, week, day_of_week)
+                    .with_context(|| format!("invalid special day on {date}"))?;
+                dated_events.extend(events);
+            }
+            date += Duration::days(1);
+        }
+
+        Ok(dated_events)
+    }
+
+    /// Bell times a special day on `date` has instead of the usual ones
+    pub(crate) fn special_bell_times(
+        &self,
+        date: NaiveDate,
+    ) -> anyhow::Result<Option<bell_times::Day>> {
+        self.special_days
+            .iter()
+            .find(|day| day.contains(date))
+            .and_then(|special_day| special_day.bell_times.as_deref())
+            .map(bell_times::Day::from_periods)
+            .transpose()
+    }
+
+    fn date_events<'a>(
+        &'a self,
+        t: &'a Timetable,
+        bell_times: &BellTimes,
+        options: &'a EventOptions,
+        date: NaiveDate,
+        week: usize,
+        day_of_week: DayOfWeek,
+    ) -> anyhow::Result<Vec<DatedEvent<'a>>> {
+        let Some(day) = t.weeks[week].days.get(&day_of_week) else {
+            return Ok(Vec::new());
+        };
+
+        let special_day = self.special_days.iter().find(|day| day.contains(date));
+        let special_bell_times = self.special_bell_times(date)?;
+
+        let day_bell_times = special_bell_times
+            .as_ref()
+            .unwrap_or_else(|| bell_times.day(day_of_week));
+        let mut events = Vec::new();
+        day_events(
+            &mut events,
+            day_of_week,
+            day,
+            week,
+            day_bell_times,
+            &options.free_periods,
+        );
+
+        let cancelled = |period| special_day.is_some_and(|day| day.cancel.contains(&period));
+        let replaced = |period| {
+            !cancelled(period) && special_day.is_some_and(|day| day.replace.contains(&period))
+        };
+        let mut dated_events: Vec<DatedEvent<'a>> = events
+            .into_iter()
+            .filter(|event| !cancelled(event.period) && !replaced(event.period))
+            .map(|mut event| {
+                // Lunch can run into a replaced half of itself
+                if let Some(replaced_start) = day_bell_times
+                    .bell_times
+                    .iter()
+                    .filter(|bell_time| replaced(bell_time.period()))
+                    .map(|bell_time| bell_time.start())
+                    .find(|start| event.start < *start && *start < event.end)
+                {
+                    event.end = replaced_start;
+                }
+                DatedEvent {
+                    date,
+                    event,
+                    replaced_by: None,
+                }
+            })
+            .collect();
+
+        // Replaced periods are shown whatever the lesson,
+        // even a free period the free period options would hide
+        if let Some(special_day) = special_day {
+            for bell_time in &day_bell_times.bell_times {
+                let period = bell_time.period();
+                let Some(lesson) = day.get(period).filter(|_| replaced(period)) else {
+                    continue;
+                };
+                dated_events.push(DatedEvent {
+                    date,
+                    event: Event {
+                        day_of_week,
+                        week,
+                        period,
+                        start: bell_time.start(),
+                        end: bell_time.end(),
+                        lesson,
+                        study: None,
+                    },
+                    replaced_by: Some(&special_day.name),
+                });
+            }
+            dated_events.sort_by_key(|dated_event| dated_event.event.start);
+        }
+
+        // Doubles are merged after the special day, so they can be cut short by it
+        merge_doubles(&mut dated_events, options.merge_doubles, |dated_event| {
+            dated_event
+                .replaced_by
+                .is_none()
+                .then_some(&mut dated_event.event)
+        });
+
+        Ok(dated_events)
+    }
+
+    /// Week of the cycle that `date` falls in, which is always week A without any weeks.
+    ///
+    /// Each term restarts the cycle at its `start_week` on its first full week,
+    /// so a term starting on a Friday spends that Friday in the same week as the Monday after.
+    /// Dates in the holidays before a term get that term's first week,
+    /// and dates after the last term keep counting on.
+    pub(crate) fn cycle_week(&self, date: NaiveDate, weeks: usize) -> usize {
+        let term = self
+            .terms
+            .iter()
+            .find(|term| date <= term.end)
+            .unwrap_or_else(|| self.terms.last().unwrap());
+
+        let first_monday = monday_of(term.start + Duration::days(6));
+        let term_week = weeks_between(first_monday, monday_of(date)).max(0);
+        (term.start_week as i64 + term_week).rem_euclid(weeks.max(1) as i64) as usize
+    }
+}
+
+/// Week of the cycle that `date` falls in, counting on from the Monday of a week A.
+/// It's always week A without any weeks.
+pub(crate) fn cycle_week_from(week_a_start: NaiveDate, date: NaiveDate, weeks: usize) -> usize {
+    weeks_between(week_a_start, monday_of(date)).rem_euclid(weeks.max(1) as i64) as usize
+}
+
+/// Monday on or before `date`
+fn monday_of(date: NaiveDate) -> NaiveDate {
+    date - Duration::days(date.weekday().num_days_from_monday() as i64)
+}
+
+fn weeks_between(from: NaiveDate, to: NaiveDate) -> i64 {
+    (to - from).num_days().div_euclid(7)
+}
+
+fn deserialize_week<'de, D>(deserializer: D) -> Result<usize, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let text = String::deserialize(deserializer)?;
+    parse_week_name(&text).map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+#[test]
+fn test_cycle_week() {
+    let date = |text: &str| NaiveDate::parse_from_str(text, "%Y-%m-%d").unwrap();
+    let calendar: TermCalendar = toml::from_str(
+        r#"
+        [[terms]]
+        start = "2023-01-27"
+        end = "2023-04-06"
+
+        [[terms]]
+        start = "2023-04-24"
+        end = "2023-06-30"
+        start_week = "B"
+        "#,
+    )
+    .unwrap();
+    calendar.validate().unwrap();
+
+    // Friday before the first full week is part of it
+    assert_eq!(calendar.cycle_week(date("2023-01-27"), 2), 0);
+    assert_eq!(calendar.cycle_week(date("2023-01-30"), 2), 0);
+    assert_eq!(calendar.cycle_week(date("2023-02-08"), 2), 1);
+    // Holidays pick up the next term's first week
+    assert_eq!(calendar.cycle_week(date("2023-04-14"), 2), 1);
+    assert_eq!(calendar.cycle_week(date("2023-04-24"), 2), 1);
+    assert_eq!(calendar.cycle_week(date("2023-05-03"), 2), 0);
+    // Past the end of the calendar
+    assert_eq!(calendar.cycle_week(date("2023-07-12"), 2), 0);
+    // Without any weeks
+    assert_eq!(calendar.cycle_week(date("2023-02-08"), 0), 0);
+
+    assert_eq!(
+        cycle_week_from(date("2023-01-30"), date("2023-02-08"), 2),
+        1
+    );
+    assert_eq!(
+        cycle_week_from(date("2023-01-30"), date("2023-01-29"), 2),
+        1
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn test_example() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("calendar.example.toml");
+    TermCalendar::load(&path).unwrap();
+}
+
+#[cfg(test)]
+#[test]
+fn test_dated_events() {
+    use crate::types::{Day, Lesson, Period, Week};
+
+    let date = |text: &str| NaiveDate::parse_from_str(text, "%Y-%m-%d").unwrap();
+    let calendar: TermCalendar = toml::from_str(
+        r#"
+        [[terms]]
+        start = "2023-04-24"
+        end = "2023-05-26"
+
+        [[holidays]]
+        name = "Anzac Day"
+        start = "2023-04-25"
+
+        [[pupil_free_days]]
+        name = "Staff Development Day"
+        start = "2023-05-08"
+        end = "2023-05-09"
+        "#,
+    )
+    .unwrap();
+    calendar.validate().unwrap();
+
+    assert_eq!(
+        calendar.status(date("2023-04-25"), 2),
+        DateStatus::Holiday("Anzac Day")
+    );
+    assert_eq!(calendar.status(date("2023-04-29"), 2), DateStatus::Weekend);
+    assert_eq!(calendar.status(date("2023-05-29"), 2), DateStatus::Holidays);
+
+    // A free period every Tuesday of week A
+    let mut week_a = Week::default();
+    week_a.days.insert(
+        DayOfWeek::Tuesday,
+        Day::from_lessons([(Period::P1, Lesson::FreePeriod)]).unwrap(),
+    );
+    let t = Timetable {
+        weeks: vec![week_a, Week::default()],
+    };
+    // Days with only free periods would be hidden otherwise
+    let options: EventOptions = toml::from_str("free_periods = { trailing = \"free\" }").unwrap();
+
+    let (from, to) = calendar.range();
+    let dates: Vec<_> = calendar
+        .dated_events(&t, &BellTimes::default(), &options, from, to)
+        .unwrap()
+        .iter()
+        .map(|event| event.date)
+        .collect();
+
+    // Anzac Day and the pupil free day are the other Tuesdays of week A
+    assert_eq!(dates, vec![date("2023-05-23")]);
+}
+
+#[cfg(test)]
+#[test]
+fn test_special_days() {
+    use crate::types::{Day, Lesson, Period, Week};
+
+    let date = |text: &str| NaiveDate::parse_from_str(text, "%Y-%m-%d").unwrap();
+    let calendar: TermCalendar = toml::from_str(
+        r#"
+        [[terms]]
+        start = "2023-04-24"
+        end = "2023-05-26"
+
+        [[special_days]]
+        name = "Athletics Carnival"
+        start = "2023-04-24"
+        replace = ["P1"]
+        cancel = ["P2"]
+
+        [[special_days]]
+        name = "Swimming Carnival"
+        start = "2023-04-25"
+        replace = ["P1", "P2"]
+
+        [[special_days]]
+        name = "Early Finish"
+        start = "2023-05-08"
+
+        [[special_days.bell_times]]
+        period = "P1"
+        start = "08:55"
+        end = "09:30"
+        "#,
+    )
+    .unwrap();
+    calendar.validate().unwrap();
+
+    let mut week_a = Week::default();
+    week_a.days.insert(
+        DayOfWeek::Monday,
+        Day::from_lessons([
+            (Period::P1, Lesson::FreePeriod),
+            (Period::P2, Lesson::FreePeriod),
+        ])
+        .unwrap(),
+    );
+    // P2 is a trailing free period
+    week_a.days.insert(
+        DayOfWeek::Tuesday,
+        Day::from_lessons([
+            (Period::P1, Lesson::class("MAT")),
+            (Period::P2, Lesson::FreePeriod),
+        ])
+        .unwrap(),
+    );
+    let t = Timetable {
+        weeks: vec![week_a, Week::default()],
+    };
+    // Days with only free periods would be hidden otherwise
+    let options: EventOptions = toml::from_str("free_periods = { trailing = \"free\" }").unwrap();
+
+    let events = |date, options: &EventOptions| {
+        calendar
+            .dated_events(&t, &BellTimes::default(), options, date, date)
+            .unwrap()
+            .iter()
+            .map(|event| {
+                (
+                    event.event.period,
+                    event.title(&Templates::default()),
+                    event.event.end.format("%H:%M").to_string(),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        events(date("2023-04-24"), &options),
+        vec![(
+            Period::P1,
+            "Athletics Carnival".to_string(),
+            "09:50".to_string()
+        )]
+    );
+    // P2 isn't in the special bell times so it doesn't happen
+    assert_eq!(
+        events(date("2023-05-08"), &options),
+        vec![(Period::P1, "Free Period".to_string(), "09:30".to_string())]
+    );
+    // The free period is hidden usually, but not when it's replaced
+    assert_eq!(
+        events(date("2023-04-25"), &EventOptions::default()),
+        vec![
+            (
+                Period::P1,
+                "Swimming Carnival".to_string(),
+                "09:50".to_string()
+            ),
+            (
+                Period::P2,
+                "Swimming Carnival".to_string(),
+                "10:45".to_string()
+            )
+        ]
+    );
+}
@@ -0,0 +1,195 @@
+use crate::types::{parse_week_name, DayOfWeek, StudentIdentity};
+use chrono::{Local, NaiveDate};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
+
+const DEFAULT_OUTPUT_DIR: &str = "timetables";
+
+/// Timetable scraper and generator
+#[derive(Debug, Parser)]
+pub(crate) struct Cli {
+    /// Config file, defaults to timetable.toml if it exists
+    #[arg(long, global = true)]
+    pub(crate) config: Option<PathBuf>,
+
+    /// Directory for cached HTML and parsed timetables
+    #[arg(long, global = true)]
+    pub(crate) cache_dir: Option<PathBuf>,
+
+    /// Scrapes and then generates when no command is given
+    #[command(subcommand)]
+    pub(crate) command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub(crate) enum Command {
+    /// Fetch timetables from the school's website into the cache
+    Scrape(ScrapeArgs),
+    /// Write timetable files from the cache
+    Generate(GenerateArgs),
+    /// Print cached timetables as text
+    Render(RenderArgs),
+    /// Search cached timetables for matching lessons
+    Query(QueryArgs),
+    /// Compare two caches or timetable JSON files
+    Diff(DiffArgs),
+    /// Check cached timetables line up with the bell times
+    Validate(ValidateArgs),
+    /// Read Class Timetable files into the cache, replacing the students' cached timetables
+    Import(ImportArgs),
+}
+
+#[derive(Debug, Args)]
+pub(crate) struct ScrapeArgs {
+    #[command(flatten)]
+    pub(crate) students: StudentFilter,
+}
+
+#[derive(Debug, Args)]
+pub(crate) struct GenerateArgs {
+    #[arg(long, short, default_value = DEFAULT_OUTPUT_DIR)]
+    pub(crate) output_dir: PathBuf,
+
+    /// Formats to write, defaults to timetable (and ics when week_a_start or calendar is set)
+    #[arg(long = "format", short, value_enum)]
+    pub(crate) formats: Vec<Format>,
+
+    /// How .timetable files are written
+    #[arg(long, value_enum, default_value_t = PlistEncoding::Xml)]
+    pub(crate) plist_encoding: PlistEncoding,
+
+    /// Fail instead of warning when generated events overlap, fall outside the bell times
+    /// or have no colour
+    #[arg(long)]
+    pub(crate) strict: bool,
+
+    #[command(flatten)]
+    pub(crate) students: StudentFilter,
+}
+
+impl Default for GenerateArgs {
+    fn default() -> Self {
+        Self {
+            output_dir: PathBuf::from(DEFAULT_OUTPUT_DIR),
+            formats: Vec::new(),
+            plist_encoding: PlistEncoding::Xml,
+            strict: false,
+            students: StudentFilter::default(),
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub(crate) struct RenderArgs {
+    #[command(flatten)]
+    pub(crate) students: StudentFilter,
+}
+
+#[derive(Debug, Args)]
+pub(crate) struct QueryArgs {
+    /// Week of the cycle as a letter, i.e. `A`
+    #[arg(long, value_parser = parse_week_name)]
+    pub(crate) week: Option<usize>,
+
+    #[arg(long, value_enum)]
+    pub(crate) day: Option<DayOfWeek>,
+
+    /// Only lessons on a date (or `today`), using the term calendar to find the week
+    #[arg(long, value_parser = parse_date)]
+    pub(crate) date: Option<NaiveDate>,
+
+    /// Subject code, optionally with the class code i.e. `COM` or `COM.1`
+    #[arg(long)]
+    pub(crate) subject: Option<String>,
+
+    #[arg(long)]
+    pub(crate) room: Option<String>,
+
+    /// Matches part of the teacher's name
+    #[arg(long)]
+    pub(crate) teacher: Option<String>,
+
+    #[command(flatten)]
+    pub(crate) students: StudentFilter,
+}
+
+fn parse_date(text: &str) -> Result<NaiveDate, String> {
+    if text.eq_ignore_ascii_case("today") {
+        return Ok(Local::now().date_naive());
+    }
+    NaiveDate::parse_from_str(text, "%Y-%m-%d")
+        .map_err(|_| format!("{text:?} is not a date like 2023-01-30 or today"))
+}
+
+#[derive(Debug, Args)]
+pub(crate) struct DiffArgs {
+    /// Cache directory or timetable JSON file
+    pub(crate) old: PathBuf,
+
+    /// Cache directory or timetable JSON file
+    pub(crate) new: PathBuf,
+
+    #[command(flatten)]
+    pub(crate) students: StudentFilter,
+}
+
+#[derive(Debug, Args)]
+pub(crate) struct ValidateArgs {
+    #[command(flatten)]
+    pub(crate) students: StudentFilter,
+}
+
+#[derive(Debug, Args)]
+pub(crate) struct ImportArgs {
+    /// `.timetable` files named like the generated ones, i.e. `123+jane+doe.timetable`
+    #[arg(required = true)]
+    pub(crate) files: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum Format {
+    /// Class Timetable app plist
+    Timetable,
+    /// iCalendar
+    Ics,
+    /// Versioned JSON of the parsed timetable
+    Json,
+}
+
+impl Format {
+    pub(crate) fn extension(self) -> &'static str {
+        match self {
+            Format::Timetable => "timetable",
+            Format::Ics => "ics",
+            Format::Json => "json",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum PlistEncoding {
+    Xml,
+    /// Smaller `bplist00` files
+    Binary,
+}
+
+#[derive(Debug, Default, Args)]
+pub(crate) struct StudentFilter {
+    /// Only include students whose id or full name matches, can be repeated
+    #[arg(long = "student", short)]
+    students: Vec<String>,
+}
+
+impl StudentFilter {
+    pub(crate) fn matches(&self, student: &StudentIdentity) -> bool {
+        if self.students.is_empty() {
+            return true;
+        }
+
+        let name = format!("{} {}", student.first, student.last).to_lowercase();
+
+        self.students
+            .iter()
+            .any(|filter| filter == &student.id || name.contains(&filter.to_lowercase()))
+    }
+}
@@ -0,0 +1,50 @@
+use crate::cache::Cache;
+use crate::json::{read_timetable_json, TimetableFile};
+use crate::types::Lesson;
+use std::path::Path;
+
+pub(crate) mod diff;
+pub(crate) mod generate;
+pub(crate) mod import;
+pub(crate) mod query;
+pub(crate) mod render;
+pub(crate) mod scrape;
+pub(crate) mod validate;
+
+/// Reads timetables from either a single JSON file or a cache directory
+fn load_timetables(path: &Path) -> anyhow::Result<Vec<TimetableFile>> {
+    anyhow::ensure!(path.exists(), "{} does not exist", path.display());
+
+    if path.is_file() {
+        Ok(vec![read_timetable_json(path)?])
+    } else {
+        Cache::open(path)?.read_timetables()
+    }
+}
+
+/// One line description of a lesson for terminal output
+fn lesson_summary(lesson: &Lesson) -> String {
+    match lesson {
+        Lesson::Present {
+            subject,
+            teacher,
+            room,
+            subject_code,
+            class_code,
+        } => {
+            let mut summary = format!("{subject_code}.{class_code} {}", subject.name);
+            if let Some(room) = room {
+                summary += &format!(" in {room}");
+            }
+            if let Some(teacher) = teacher {
+                summary += &format!(" with {}", teacher.name());
+            }
+            summary
+        }
+        Lesson::FreePeriod => "Free Period".to_string(),
+        Lesson::AbsentPeriod => "Absent".to_string(),
+        Lesson::Recess => "Recess".to_string(),
+        Lesson::Lunch1 => "Lunch 1".to_string(),
+        Lesson::Lunch2 => "Lunch 2".to_string(),
+    }
+}
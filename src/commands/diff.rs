@@ -0,0 +1,96 @@
+use super::{lesson_summary, load_timetables};
+use crate::cli::DiffArgs;
+use crate::types::{week_name, Lesson, Week};
+use std::collections::{BTreeMap, BTreeSet};
+
+pub(crate) fn diff(args: &DiffArgs) -> anyhow::Result<()> {
+    let old: BTreeMap<_, _> = load_timetables(&args.old)?
+        .into_iter()
+        .filter(|file| args.students.matches(&file.student))
+        .map(|file| (file.student.id.clone(), file))
+        .collect();
+    let new: BTreeMap<_, _> = load_timetables(&args.new)?
+        .into_iter()
+        .filter(|file| args.students.matches(&file.student))
+        .map(|file| (file.student.id.clone(), file))
+        .collect();
+
+    for (id, old_file) in &old {
+        let student = &old_file.student;
+
+        let Some(new_file) = new.get(id) else {
+            println!("- {} {} ({})", student.first, student.last, id);
+            continue;
+        };
+
+        let old_weeks = &old_file.timetable.weeks;
+        let new_weeks = &new_file.timetable.weeks;
+        let empty_week = Week::default();
+
+        let mut changes = Vec::new();
+        for week in 0..old_weeks.len().max(new_weeks.len()) {
+            diff_week(
+                &mut changes,
+                week_name(week),
+                old_weeks.get(week).unwrap_or(&empty_week),
+                new_weeks.get(week).unwrap_or(&empty_week),
+            );
+        }
+
+        if !changes.is_empty() {
+            println!("~ {} {} ({})", student.first, student.last, id);
+            for change in changes {
+                println!("    {change}");
+            }
+        }
+    }
+
+    for (id, new_file) in &new {
+        if !old.contains_key(id) {
+            let student = &new_file.student;
+            println!("+ {} {} ({})", student.first, student.last, id);
+        }
+    }
+
+    Ok(())
+}
+
+fn diff_week(changes: &mut Vec<String>, week_name: char, old: &Week, new: &Week) {
+    let days = old
+        .days
+        .keys()
+        .chain(new.days.keys())
+        .collect::<BTreeSet<_>>();
+
+    for day_of_week in days {
+        let old_day = old.days.get(day_of_week);
+        let new_day = new.days.get(day_of_week);
+
+        let periods = old_day
+            .into_iter()
+            .chain(new_day)
+            .flat_map(|day| day.lessons().map(|(period, _)| period))
+            .collect::<BTreeSet<_>>();
+
+        for period in periods {
+            let old_lesson = old_day.and_then(|day| day.get(period));
+            let new_lesson = new_day.and_then(|day| day.get(period));
+            if old_lesson == new_lesson {
+                continue;
+            }
+
+            changes.push(format!(
+                "Week {} {:?} {}: {} -> {}",
+                week_name,
+                day_of_week,
+                period,
+                summary(old_lesson),
+                summary(new_lesson)
+            ));
+        }
+    }
+}
+
+fn summary(lesson: Option<&Lesson>) -> String {
+    lesson.map_or_else(|| "nothing".to_string(), lesson_summary)
+}
@@ -0,0 +1,179 @@
+use crate::cache::{file_stem, Cache};
+use crate::calendar::cycle_week_from;
+use crate::cli::{Format, GenerateArgs, PlistEncoding};
+use crate::config::Config;
+use crate::generator::check::check_timetable;
+use crate::generator::colours::{event_colours, hex};
+use crate::generator::ics::{gen_dated_ics, gen_timetable_ics};
+use crate::generator::timetable_events;
+use crate::generator::{gen_timetable_plist, SelectedWeek};
+use crate::json::write_export_json;
+use crate::plist::{to_binary, to_xml};
+use anyhow::Context;
+use chrono::{Local, Utc};
+
+pub(crate) fn generate(config: &Config, cache: &Cache, args: &GenerateArgs) -> anyhow::Result<()> {
+    let week_a_start = config.week_a_start()?;
+    let bell_times = config.bell_times()?;
+    let special_lessons = config.special_lessons()?;
+    let calendar = config.calendar()?;
+
+    let mut formats = args.formats.clone();
+    if formats.is_empty() {
+        formats.push(Format::Timetable);
+        if week_a_start.is_some() || calendar.is_some() {
+            formats.push(Format::Ics);
+        }
+    }
+
+    let now = Utc::now();
+    let today = now.with_timezone(&Local).date_naive();
+
+    std::fs::create_dir_all(&args.output_dir)?;
+
+    let files = cache.read_timetables()?;
+
+    // Every student is registered, not only the ones being generated,
+    // so colours don't depend on which students were picked
+    let mut colours = cache.read_colour_registry()?;
+    let mut changed = false;
+    for file in &files {
+        changed |= colours.register(&file.timetable);
+    }
+    if changed {
+        cache.write_colour_registry(&colours)?;
+    }
+    let palette = colours.palette(config.colours(), special_lessons)?;
+    let templates = config.templates();
+    let options = config.events();
+
+    // Checked before anything's written, so a strict run doesn't leave some files behind
+    let mut invalid = 0;
+    for file in &files {
+        if !args.students.matches(&file.student) {
+            continue;
+        }
+
+        let context = || {
+            format!(
+                "failed to generate events for {} {} ({})",
+                file.student.first, file.student.last, file.student.id
+            )
+        };
+        let events =
+            timetable_events(&file.timetable, &bell_times, options).with_context(context)?;
+        // Dated lessons are only checked when they're written to iCalendar files
+        let problems = check_timetable(
+            &file.timetable,
+            &bell_times,
+            calendar.as_ref().filter(|_| formats.contains(&Format::Ics)),
+            &palette,
+            templates,
+            options,
+        )
+        .with_context(context)?;
+
+        if !problems.is_empty() {
+            invalid += 1;
+            eprintln!(
+                "{} {} ({})",
+                file.student.first, file.student.last, file.student.id
+            );
+            for problem in problems {
+                eprintln!("    {problem}");
+            }
+        }
+
+        // No file can be written without a colour for every title, so that's never a warning
+        event_colours(&events, &palette, templates).with_context(|| {
+            format!(
+                "failed to colour events for {} {} ({})",
+                file.student.first, file.student.last, file.student.id
+            )
+        })?;
+    }
+    anyhow::ensure!(
+        !args.strict || invalid == 0,
+        "{invalid} timetables have invalid events"
+    );
+
+    for file in files {
+        if !args.students.matches(&file.student) {
+            continue;
+        }
+
+        for format in &formats {
+            let path = args.output_dir.join(format!(
+                "{}.{}",
+                file_stem(&file.student),
+                format.extension()
+            ));
+
+            match format {
+                Format::Timetable => {
+                    // Without a calendar or week A to count from, start on week A
+                    let weeks = file.timetable.weeks.len();
+                    let week = match (&calendar, week_a_start) {
+                        (Some(calendar), _) => calendar.cycle_week(today, weeks),
+                        (None, Some(week_a_start)) => cycle_week_from(week_a_start, today, weeks),
+                        (None, None) => 0,
+                    };
+                    let selected_week = SelectedWeek { week, updated: now };
+
+                    let plist = gen_timetable_plist(
+                        &file.timetable,
+                        &bell_times,
+                        &selected_week,
+                        &palette,
+                        templates,
+                        options,
+                    )
+                    .with_context(|| format!("failed to generate {}", path.display()))?;
+                    let bytes = match args.plist_encoding {
+                        PlistEncoding::Xml => to_xml(&plist).into_bytes(),
+                        PlistEncoding::Binary => to_binary(&plist),
+                    };
+                    std::fs::write(path, bytes)?;
+                }
+                Format::Ics => {
+                    // A calendar gives every lesson its own date, so days off are left out
+                    let ics = match (&calendar, week_a_start) {
+                        (Some(calendar), _) => {
+                            let (from, to) = calendar.range();
+                            calendar
+                                .dated_events(&file.timetable, &bell_times, options, from, to)
+                                .and_then(|events| {
+                                    gen_dated_ics(&events, &palette, templates, &file.student.id)
+                                })
+                        }
+                        (None, Some(week_a_start)) => gen_timetable_ics(
+                            &file.timetable,
+                            &bell_times,
+                            &palette,
+                            templates,
+                            options,
+                            week_a_start,
+                            &file.student.id,
+                        ),
+                        (None, None) => Err(anyhow::anyhow!(
+                            "week_a_start or calendar must be set to generate iCalendar files"
+                        )),
+                    }
+                    .with_context(|| format!("failed to generate {}", path.display()))?;
+                    std::fs::write(path, ics)?;
+                }
+                Format::Json => {
+                    let colours = timetable_events(&file.timetable, &bell_times, options)
+                        .and_then(|events| event_colours(&events, &palette, templates))
+                        .with_context(|| format!("failed to generate {}", path.display()))?
+                        .into_iter()
+                        .map(|(title, colour)| (title, hex(colour)))
+                        .collect();
+                    write_export_json(&path, &file, &colours)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,176 @@
+use crate::cache::{parse_file_stem, Cache};
+use crate::cli::ImportArgs;
+use crate::config::Config;
+use crate::generator::colours::{event_colours, hex};
+use crate::generator::parse::parse_timetable_plist;
+use crate::generator::timetable_events;
+use crate::json::TimetableFile;
+use crate::plist::from_xml;
+use crate::types::{Day, Lesson, Subject, Teacher, Timetable, Week};
+use anyhow::Context;
+use std::collections::HashMap;
+
+pub(crate) fn import(config: &Config, cache: &Cache, args: &ImportArgs) -> anyhow::Result<()> {
+    let bell_times = config.bell_times()?;
+    let rules = config.special_lessons()?;
+    let cached = cache.read_timetables()?;
+
+    // `.timetable` files only have what the title template shows,
+    // so everything else comes from anyone in the same class
+    let mut classes: HashMap<(&str, &str), (&Subject, &Option<Teacher>)> = HashMap::new();
+    let mut codes: HashMap<(&str, &str), &str> = HashMap::new();
+    for (_, lesson) in cached
+        .iter()
+        .flat_map(|file| &file.timetable.weeks)
+        .flat_map(|week| week.days.values())
+        .flat_map(|day| day.lessons())
+    {
+        if let Lesson::Present {
+            subject,
+            teacher,
+            subject_code,
+            class_code,
+            ..
+        } = lesson
+        {
+            classes.insert((subject_code, class_code), (subject, teacher));
+            codes.insert((&subject.name, class_code), subject_code);
+        }
+    }
+
+    let mut imported = Vec::new();
+    for path in &args.files {
+        let student = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(parse_file_stem)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "{} should be named like id+first+last.timetable",
+                    path.display()
+                )
+            })?;
+        // Names in the cache haven't lost their capitals
+        let student = cached
+            .iter()
+            .map(|file| &file.student)
+            .find(|cached| cached.id == student.id)
+            .cloned()
+            .unwrap_or(student);
+
+        let bytes =
+            std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        anyhow::ensure!(
+            !bytes.starts_with(b"bplist"),
+            "{} is a binary plist, only XML ones can be imported",
+            path.display()
+        );
+        let text = String::from_utf8(bytes)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let parsed = from_xml(&text)
+            .and_then(|value| {
+                parse_timetable_plist(
+                    &value,
+                    &bell_times,
+                    rules,
+                    config.templates(),
+                    &config.events().free_periods,
+                )
+            })
+            .with_context(|| format!("failed to parse {}", path.display()))?;
+
+        let weeks = parsed
+            .timetable
+            .weeks
+            .iter()
+            .map(|week| {
+                let days = week
+                    .days
+                    .iter()
+                    .map(|(day_of_week, day)| Ok((*day_of_week, fill_day(day, &classes, &codes)?)))
+                    .collect::<anyhow::Result<_>>()?;
+                Ok(Week { days })
+            })
+            .collect::<anyhow::Result<_>>()
+            .with_context(|| format!("failed to import {}", path.display()))?;
+
+        imported.push((student, Timetable { weeks }, parsed.colours));
+    }
+
+    let mut registry = cache.read_colour_registry()?;
+    for (_, timetable, _) in &imported {
+        registry.register(timetable);
+    }
+    let palette = registry.palette(config.colours(), rules)?;
+
+    for (student, timetable, app_colours) in imported {
+        println!("{} {} ({})", student.first, student.last, student.id);
+
+        // Colours changed in the app don't survive being generated again
+        let colours = timetable_events(&timetable, &bell_times, config.events())
+            .and_then(|events| event_colours(&events, &palette, config.templates()))?;
+        for (title, colour) in colours {
+            match app_colours.get(&title) {
+                Some(app_colour) if hex(*app_colour) != hex(colour) => println!(
+                    "    {} was {} in the app and will be {}",
+                    title,
+                    hex(*app_colour),
+                    hex(colour)
+                ),
+                _ => {}
+            }
+        }
+
+        cache.write_timetable(&TimetableFile::new(student, timetable))?;
+    }
+
+    Ok(())
+}
+
+/// Fills in classes from the cache, finding subject codes from names
+/// when the title template only has names
+fn fill_day(
+    day: &Day,
+    classes: &HashMap<(&str, &str), (&Subject, &Option<Teacher>)>,
+    codes: &HashMap<(&str, &str), &str>,
+) -> anyhow::Result<Day> {
+    let mut lessons = Vec::new();
+    for (period, lesson) in day.lessons() {
+        let lesson = match lesson {
+            Lesson::Present {
+                subject,
+                room,
+                subject_code,
+                class_code,
+                ..
+            } => {
+                let subject_code = match subject_code.as_str() {
+                    "" => *codes
+                        .get(&(subject.name.as_str(), class_code.as_str()))
+                        .ok_or_else(|| {
+                            anyhow::anyhow!(
+                                "no one in the cache has {} class {:?}, so its subject code isn't known",
+                                subject.name,
+                                class_code
+                            )
+                        })?,
+                    subject_code => subject_code,
+                };
+                match classes.get(&(subject_code, class_code.as_str())) {
+                    Some((subject, teacher)) => Lesson::Present {
+                        subject: (*subject).clone(),
+                        teacher: (*teacher).clone(),
+                        room: room.clone(),
+                        subject_code: subject_code.to_string(),
+                        class_code: class_code.clone(),
+                    },
+                    None => lesson.clone(),
+                }
+            }
+            _ => lesson.clone(),
+        };
+        lessons.push((period, lesson));
+    }
+
+    Ok(Day::from_lessons(lessons).expect("periods come from a day"))
+}
@@ -0,0 +1,144 @@
+use crate::cache::Cache;
+use crate::calendar::DateStatus;
+use crate::cli::QueryArgs;
+use crate::config::Config;
+use crate::generator::{timetable_events, Event};
+use crate::types::{week_name, Lesson};
+use anyhow::Context;
+
+pub(crate) fn query(config: &Config, cache: &Cache, args: &QueryArgs) -> anyhow::Result<()> {
+    let bell_times = config.bell_times()?;
+    let templates = config.templates();
+    let calendar = match args.date {
+        Some(_) => Some(
+            config
+                .calendar()?
+                .ok_or_else(|| anyhow::anyhow!("a calendar must be set to query by date"))?,
+        ),
+        None => None,
+    };
+
+    for file in cache.read_timetables()? {
+        if !args.students.matches(&file.student) {
+            continue;
+        }
+
+        // Lessons on a date come from the calendar so special days are followed,
+        // and the week can be different for each student if their cycles are different lengths
+        let events: Vec<_> = match (args.date, &calendar) {
+            (Some(date), Some(calendar)) => {
+                let status = calendar.status(date, file.timetable.weeks.len());
+                if !matches!(status, DateStatus::School { .. }) {
+                    println!(
+                        "{} {} ({})  No school on {}: {}",
+                        file.student.first, file.student.last, file.student.id, date, status
+                    );
+                    continue;
+                }
+
+                calendar
+                    .dated_events(&file.timetable, &bell_times, config.events(), date, date)
+                    .with_context(|| format!("invalid timetable for student {}", file.student.id))?
+                    .into_iter()
+                    .map(|dated_event| {
+                        (
+                            dated_event.event,
+                            dated_event.title(templates),
+                            dated_event.info(templates),
+                        )
+                    })
+                    .collect()
+            }
+            _ => timetable_events(&file.timetable, &bell_times, config.events())
+                .with_context(|| format!("invalid timetable for student {}", file.student.id))?
+                .into_iter()
+                .map(|event| (event, templates.title(&event), templates.info(&event)))
+                .collect(),
+        };
+
+        for (event, title, info) in events {
+            if !matches_query(&event, args) {
+                continue;
+            }
+
+            println!(
+                "{} {} ({})  Week {} {:?} {}-{}  {}{}",
+                file.student.first,
+                file.student.last,
+                file.student.id,
+                week_name(event.week),
+                event.day_of_week,
+                event.start.format("%H:%M"),
+                event.end.format("%H:%M"),
+                title,
+                info.map(|info| format!("  {info}")).unwrap_or_default()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn matches_query(event: &Event<'_>, args: &QueryArgs) -> bool {
+    if let Some(week) = args.week {
+        if event.week != week {
+            return false;
+        }
+    }
+
+    if let Some(day) = args.day {
+        if event.day_of_week != day {
+            return false;
+        }
+    }
+
+    // Everything else only applies to actual classes
+    if args.subject.is_none() && args.room.is_none() && args.teacher.is_none() {
+        return true;
+    }
+
+    let Lesson::Present {
+        teacher,
+        room,
+        subject_code,
+        class_code,
+        ..
+    } = event.lesson
+    else {
+        return false;
+    };
+
+    if let Some(subject) = &args.subject {
+        let matches = match subject.split_once('.') {
+            Some((query_subject, query_class)) => {
+                query_subject.eq_ignore_ascii_case(subject_code)
+                    && query_class.eq_ignore_ascii_case(class_code)
+            }
+            None => subject.eq_ignore_ascii_case(subject_code),
+        };
+        if !matches {
+            return false;
+        }
+    }
+
+    if let Some(query_room) = &args.room {
+        if !room
+            .as_ref()
+            .is_some_and(|room| room.eq_ignore_ascii_case(query_room))
+        {
+            return false;
+        }
+    }
+
+    if let Some(query_teacher) = &args.teacher {
+        let query_teacher = query_teacher.to_lowercase();
+        if !teacher
+            .as_ref()
+            .is_some_and(|teacher| teacher.name().to_lowercase().contains(&query_teacher))
+        {
+            return false;
+        }
+    }
+
+    true
+}
@@ -0,0 +1,48 @@
+use crate::cache::Cache;
+use crate::cli::RenderArgs;
+use crate::config::Config;
+use crate::generator::timetable_events;
+use crate::types::week_name;
+use anyhow::Context;
+
+pub(crate) fn render(config: &Config, cache: &Cache, args: &RenderArgs) -> anyhow::Result<()> {
+    let bell_times = config.bell_times()?;
+    let templates = config.templates();
+
+    for file in cache.read_timetables()? {
+        if !args.students.matches(&file.student) {
+            continue;
+        }
+
+        println!(
+            "{} {} ({})",
+            file.student.first, file.student.last, file.student.id
+        );
+
+        let mut heading = None;
+        for event in timetable_events(&file.timetable, &bell_times, config.events())
+            .with_context(|| format!("invalid timetable for student {}", file.student.id))?
+        {
+            if heading != Some((event.week, event.day_of_week)) {
+                heading = Some((event.week, event.day_of_week));
+                println!("  Week {} {:?}", week_name(event.week), event.day_of_week);
+            }
+
+            print!(
+                "    {:<8} {}-{}  {}",
+                event.period,
+                event.start.format("%H:%M"),
+                event.end.format("%H:%M"),
+                templates.title(&event)
+            );
+            match templates.info(&event) {
+                Some(info) => println!("  {info}"),
+                None => println!(),
+            }
+        }
+
+        println!();
+    }
+
+    Ok(())
+}
@@ -0,0 +1,79 @@
+use crate::cache::Cache;
+use crate::cli::StudentFilter;
+use crate::config::Config;
+use crate::json::TimetableFile;
+use crate::scraper::{self, scrape_timetable_page};
+use indicatif::{ProgressBar, ProgressStyle};
+use reqwest::blocking::Client;
+use reqwest::cookie::Jar;
+use std::sync::Arc;
+
+const USER_AGENT: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/14.1.1 Safari/605.1.15";
+// const DELAY: Duration = Duration::from_millis(85);
+
+pub(crate) fn scrape(config: &Config, cache: &Cache, filter: &StudentFilter) -> anyhow::Result<()> {
+    let host = config.host()?;
+    let timetable_uuid = config.timetable_uuid()?;
+    let layout = config.layout()?;
+    let rules = config.special_lessons()?;
+    layout.check_against(&config.bell_times()?)?;
+
+    let jar = Jar::default();
+
+    let cookie_str = format!(
+        "TIMETABLE={}; Domain={}",
+        config.cookie()?,
+        host.strip_prefix("https://").unwrap_or(&host)
+    );
+    jar.add_cookie_str(&cookie_str, &host.parse()?);
+
+    let client = Client::builder()
+        .cookie_store(true)
+        .cookie_provider(Arc::new(jar))
+        .user_agent(USER_AGENT)
+        .build()?;
+
+    let mut students = scraper::scrape_student_identities(&client, &host, &timetable_uuid)?;
+    students.retain(|student| filter.matches(student));
+
+    let bar = ProgressBar::new(students.len() as u64);
+    bar.set_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.green} [{wide_bar:.red}] [{pos:>3.blue}/{len:>3.blue}] Voting Ends In: {eta:.bold} {msg:.white.bold}")?
+            .progress_chars(" ඞ "),
+    );
+
+    let mut failures = Vec::new();
+    let student_count = students.len();
+
+    for student in students {
+        let text = client
+            .get(format!("{}/{}/{}", host, timetable_uuid, student.id))
+            .send()?
+            .text()?;
+
+        cache.write_html(&student, &text)?;
+
+        // A single odd page shouldn't stop everyone else from being scraped
+        match scrape_timetable_page(&text, &layout, rules) {
+            Ok(timetable) => cache.write_timetable(&TimetableFile::new(student, timetable))?,
+            Err(e) => failures.push(e.with_student(&student.id)),
+        }
+
+        bar.inc(1);
+    }
+    bar.finish_with_message("Red was the impostor...");
+
+    if !failures.is_empty() {
+        eprintln!(
+            "\n{} of {} timetables failed to scrape:",
+            failures.len(),
+            student_count
+        );
+        for failure in &failures {
+            eprintln!("  {failure}");
+        }
+    }
+
+    Ok(())
+}
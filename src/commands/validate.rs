@@ -0,0 +1,65 @@
+use crate::cache::Cache;
+use crate::cli::ValidateArgs;
+use crate::config::Config;
+use crate::generator::check::check_timetable;
+use crate::generator::check_day;
+
+pub(crate) fn validate(config: &Config, cache: &Cache, args: &ValidateArgs) -> anyhow::Result<()> {
+    let bell_times = config.bell_times()?;
+    config.layout()?.check_against(&bell_times)?;
+    let special_lessons = config.special_lessons()?;
+    let calendar = config.calendar()?;
+    let templates = config.templates();
+    let options = config.events();
+
+    // Colours are worked out the same way as when generating, without saving new subjects
+    let files = cache.read_timetables()?;
+    let mut colours = cache.read_colour_registry()?;
+    for file in &files {
+        colours.register(&file.timetable);
+    }
+    let palette = colours.palette(config.colours(), special_lessons)?;
+
+    let mut invalid = 0;
+    for file in &files {
+        if !args.students.matches(&file.student) {
+            continue;
+        }
+
+        let mut problems = Vec::new();
+        for (week, days) in file.timetable.weeks.iter().enumerate() {
+            for (day_of_week, day) in &days.days {
+                if let Err(e) = check_day(*day_of_week, day, week, bell_times.day(*day_of_week)) {
+                    problems.push(e.to_string());
+                }
+            }
+        }
+
+        // Events can only be made once every lesson has bell times
+        if problems.is_empty() {
+            problems = check_timetable(
+                &file.timetable,
+                &bell_times,
+                calendar.as_ref(),
+                &palette,
+                templates,
+                options,
+            )?;
+        }
+
+        if !problems.is_empty() {
+            invalid += 1;
+            println!(
+                "{} {} ({})",
+                file.student.first, file.student.last, file.student.id
+            );
+            for problem in problems {
+                println!("    {problem}");
+            }
+        }
+    }
+
+    anyhow::ensure!(invalid == 0, "{invalid} timetables failed validation");
+
+    Ok(())
+}
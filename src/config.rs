@@ -0,0 +1,153 @@
+use crate::calendar::TermCalendar;
+use crate::generator::bell_times::BellTimes;
+use crate::generator::colours::ColourConfig;
+use crate::generator::template::Templates;
+use crate::generator::EventOptions;
+use crate::scraper::{LayoutProfile, LessonRules};
+use chrono::{Datelike, NaiveDate, Weekday};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_CONFIG_PATH: &str = "timetable.toml";
+
+/// Settings read from `timetable.toml`.
+/// Anything left out falls back to the matching environment variable.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Config {
+    host: Option<String>,
+    timetable_uuid: Option<String>,
+    cookie: Option<String>,
+    cache_dir: Option<PathBuf>,
+    /// Monday of any week A (the first week of the cycle), needed for date-based outputs like iCalendar
+    week_a_start: Option<NaiveDate>,
+    /// TOML or JSON file of bell times, relative to this config file
+    bell_times: Option<PathBuf>,
+    /// TOML file describing the rows of the website's timetable, relative to this config file
+    layout: Option<PathBuf>,
+    /// TOML file of term dates and holidays, relative to this config file
+    calendar: Option<PathBuf>,
+    #[serde(default)]
+    colours: ColourConfig,
+    #[serde(default)]
+    templates: Templates,
+    #[serde(default)]
+    events: EventOptions,
+    #[serde(default)]
+    special_lessons: LessonRules,
+
+    /// Directory containing the config file, which relative paths are resolved against
+    #[serde(skip)]
+    dir: PathBuf,
+}
+
+impl Config {
+    /// Loads the config at `path`, or `timetable.toml` if it exists when no path is given
+    pub(crate) fn load(path: Option<&Path>) -> anyhow::Result<Self> {
+        let path = match path {
+            Some(path) => path,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => Path::new(DEFAULT_CONFIG_PATH),
+            None => return Ok(Self::default()),
+        };
+
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("failed to read {}: {}", path.display(), e))?;
+        let mut config: Self = toml::from_str(&text)
+            .map_err(|e| anyhow::anyhow!("failed to parse {}: {}", path.display(), e))?;
+        config.dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        config
+            .templates
+            .set_special_lessons(&config.special_lessons);
+
+        Ok(config)
+    }
+
+    pub(crate) fn host(&self) -> anyhow::Result<String> {
+        config_or_env(&self.host, "HOST")
+    }
+
+    pub(crate) fn timetable_uuid(&self) -> anyhow::Result<String> {
+        config_or_env(&self.timetable_uuid, "TIMETABLE_UUID")
+    }
+
+    pub(crate) fn cookie(&self) -> anyhow::Result<String> {
+        config_or_env(&self.cookie, "COOKIE")
+    }
+
+    pub(crate) fn cache_dir(&self) -> PathBuf {
+        match &self.cache_dir {
+            Some(cache_dir) => cache_dir.clone(),
+            None => dotenv::var("CACHE_DIR")
+                .unwrap_or_else(|_| "cache".to_string())
+                .into(),
+        }
+    }
+
+    pub(crate) fn week_a_start(&self) -> anyhow::Result<Option<NaiveDate>> {
+        let date = match (self.week_a_start, dotenv::var("WEEK_A_START")) {
+            (Some(date), _) => date,
+            (None, Ok(date)) => NaiveDate::parse_from_str(&date, "%Y-%m-%d")?,
+            (None, Err(_)) => return Ok(None),
+        };
+
+        anyhow::ensure!(
+            date.weekday() == Weekday::Mon,
+            "week_a_start must be a Monday"
+        );
+
+        Ok(Some(date))
+    }
+
+    /// Bell times from the configured file, or the built in ones if there isn't one
+    pub(crate) fn bell_times(&self) -> anyhow::Result<BellTimes> {
+        match &self.bell_times {
+            Some(path) => BellTimes::load(&self.dir.join(path)),
+            None => Ok(BellTimes::default()),
+        }
+    }
+
+    /// Layout from the configured file, or the built in one if there isn't one
+    pub(crate) fn layout(&self) -> anyhow::Result<LayoutProfile> {
+        match &self.layout {
+            Some(path) => LayoutProfile::load(&self.dir.join(path)),
+            None => Ok(LayoutProfile::default()),
+        }
+    }
+
+    pub(crate) fn colours(&self) -> &ColourConfig {
+        &self.colours
+    }
+
+    pub(crate) fn special_lessons(&self) -> anyhow::Result<&LessonRules> {
+        self.special_lessons.validate()?;
+        Ok(&self.special_lessons)
+    }
+
+    pub(crate) fn templates(&self) -> &Templates {
+        &self.templates
+    }
+
+    pub(crate) fn events(&self) -> &EventOptions {
+        &self.events
+    }
+
+    pub(crate) fn calendar(&self) -> anyhow::Result<Option<TermCalendar>> {
+        self.calendar
+            .as_ref()
+            .map(|path| TermCalendar::load(&self.dir.join(path)))
+            .transpose()
+    }
+}
+
+fn config_or_env(value: &Option<String>, var: &str) -> anyhow::Result<String> {
+    match value {
+        Some(value) => Ok(value.clone()),
+        None => dotenv::var(var).map_err(|_| {
+            anyhow::anyhow!(
+                "{} must be set in the config file or as the {} environment variable",
+                var.to_lowercase(),
+                var
+            )
+        }),
+    }
+}
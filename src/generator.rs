@@ -1,199 +1,468 @@
 use self::bell_times::BellTimes;
-use crate::types::{Day, DayOfWeek, Lesson, Timetable};
-use chrono::Timelike;
+use self::colours::{event_colours, Palette};
+use self::free_periods::{FreeEvent, FreePeriods, Study};
+use self::merge::{merge_doubles, MergeDoubles};
+use self::template::Templates;
+use crate::plist::{Dict, Value};
+use crate::types::{week_name, Day, DayOfWeek, Lesson, Period, Timetable};
+use chrono::{DateTime, NaiveTime, Timelike, Utc};
+use serde::Deserialize;
 use std::collections::BTreeSet;
-mod bell_times;
-
-pub(crate) fn gen_timetable_xml(t: &Timetable) -> String {
-    let mut xml = String::new();
-    xml += r#"<?xml version="1.0" encoding="UTF-8"?><!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd"><plist version="1.0"><dict>"#; // Header
-
-    xml += "<key>Settings</key><dict><key>ColorSettings</key><dict>";
-    let mut subjects = BTreeSet::new();
-
-    let mut assembly_subject_class_codes = None;
-
-    // Generate list of subjects for colors
-    for day in t.week_a.days.values() {
-        for lesson in &day.lessons {
-            if let Lesson::Present {
-                subject,
-                class_code,
-                subject_code,
-                ..
-            } = lesson
-            {
-                if subject.name == "Assembly" {
-                    assembly_subject_class_codes = Some((subject_code, class_code));
-                    continue;
-                }
-
-                subjects.insert(format!("{}.{}", subject_code, class_code));
-            }
+pub(crate) mod bell_times;
+pub(crate) mod check;
+pub(crate) mod colours;
+pub(crate) mod free_periods;
+pub(crate) mod ics;
+pub(crate) mod merge;
+pub(crate) mod parse;
+pub(crate) mod template;
+
+/// `[events]` in the config file, which changes the events every exporter writes
+///
+/// ```toml
+/// [events]
+/// # never, within_breaks or across_breaks
+/// merge_doubles = "within_breaks"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct EventOptions {
+    #[serde(default)]
+    pub(crate) merge_doubles: MergeDoubles,
+    #[serde(default)]
+    pub(crate) free_periods: FreePeriods,
+}
+
+/// The week the app should open on, as of when it was worked out
+pub(crate) struct SelectedWeek {
+    /// Index of the week in the cycle, 0 being week A
+    pub(crate) week: usize,
+    pub(crate) updated: DateTime<Utc>,
+}
+
+/// Builds a Class Timetable plist, which can be written as XML or binary
+pub(crate) fn gen_timetable_plist(
+    t: &Timetable,
+    bell_times: &BellTimes,
+    selected_week: &SelectedWeek,
+    palette: &Palette,
+    templates: &Templates,
+    options: &EventOptions,
+) -> anyhow::Result<Value> {
+    let events = timetable_events(t, bell_times, options)?;
+
+    // Every title from every week gets a colour, including breaks and assemblies
+    let colours = event_colours(&events, palette, templates)?;
+
+    let colour_settings: Dict = colours
+        .into_iter()
+        .map(|(title, srgb)| {
+            let components = [srgb.r, srgb.g, srgb.b]
+                .into_iter()
+                .map(|component| Value::Real(component.into()))
+                .collect::<Vec<_>>();
+            (title, Value::Array(components))
+        })
+        .collect();
+
+    let mut settings = Dict::new();
+    settings.insert("ColorSettings".to_string(), colour_settings.into());
+    settings.insert(
+        "NumberOfWeeks".to_string(),
+        Value::Integer(t.weeks.len() as i64),
+    );
+    settings.insert(
+        "SelectedWeek".to_string(),
+        Value::Integer(selected_week.week as i64),
+    );
+    settings.insert(
+        "SelectedWeekUpdateDate".to_string(),
+        selected_week.updated.into(),
+    );
+    settings.insert("WeekendDaysAreActive".to_string(), false.into());
+
+    let mut timetable = Dict::new();
+    timetable.insert("Settings".to_string(), settings.into());
+    timetable.insert(
+        "WeekEvents".to_string(),
+        Value::Array(
+            events
+                .iter()
+                .map(|event| event_dict(event, templates))
+                .collect(),
+        ),
+    );
+
+    check_colour_keys(&timetable)?;
+
+    Ok(Value::Dict(timetable))
+}
+
+/// Fails if any of a built plist's `WeekEvents` has a title without a `ColorSettings` key
+fn check_colour_keys(timetable: &Dict) -> anyhow::Result<()> {
+    let colour_settings = timetable
+        .get("Settings")
+        .and_then(Value::as_dict)
+        .and_then(|settings| settings.get("ColorSettings"))
+        .and_then(Value::as_dict)
+        .ok_or_else(|| anyhow::anyhow!("timetable has no ColorSettings"))?;
+    let events = timetable
+        .get("WeekEvents")
+        .and_then(Value::as_array)
+        .ok_or_else(|| anyhow::anyhow!("timetable has no WeekEvents"))?;
+
+    let missing: BTreeSet<_> = events
+        .iter()
+        .filter_map(|event| event.as_dict()?.get("title")?.as_str())
+        .filter(|title| !colour_settings.contains_key(*title))
+        .collect();
+    anyhow::ensure!(
+        missing.is_empty(),
+        "no colour for {}",
+        missing.into_iter().collect::<Vec<_>>().join(", ")
+    );
+
+    Ok(())
+}
+
+/// A single timetabled block with its resolved bell times
+#[derive(Clone, Copy)]
+pub(crate) struct Event<'a> {
+    pub(crate) day_of_week: DayOfWeek,
+    /// Index of the week in the cycle, 0 being week A
+    pub(crate) week: usize,
+    /// Period the event starts in
+    pub(crate) period: Period,
+    pub(crate) start: NaiveTime,
+    pub(crate) end: NaiveTime,
+    pub(crate) lesson: &'a Lesson,
+    /// Set when a free period is shown as study
+    pub(crate) study: Option<&'a Study>,
+}
+
+/// Pairs every lesson with its bell times, failing if a lesson is in a period
+/// which doesn't exist on that day
+pub(crate) fn timetable_events<'a>(
+    t: &'a Timetable,
+    bell_times: &BellTimes,
+    options: &'a EventOptions,
+) -> anyhow::Result<Vec<Event<'a>>> {
+    let mut events = Vec::new();
+    for (week, days) in t.weeks.iter().enumerate() {
+        for (day_of_week, day) in &days.days {
+            let bell_times = bell_times.day(*day_of_week);
+            check_day(*day_of_week, day, week, bell_times)?;
+            day_events(
+                &mut events,
+                *day_of_week,
+                day,
+                week,
+                bell_times,
+                &options.free_periods,
+            );
         }
     }
+    merge_doubles(&mut events, options.merge_doubles, |event| Some(event));
+    Ok(events)
+}
 
-    let mut hue = 0;
-    let step = 360 / (subjects.len() + 1);
-
-    // Generate colors equal distance apart for each subject
-    for subject in subjects {
-        let srgb = gen_color(hue);
-        xml += &format!(
-            r#"<key>{}</key>
-        <array>
-            <real>{}</real>
-            <real>{}</real>
-            <real>{}</real>
-        </array>"#,
-            subject, srgb.r, srgb.g, srgb.b
-        );
-        hue += step;
+/// Fails if a day has a lesson in a period without any bell times.
+/// Free periods are fine, since they don't need to be shown.
+pub(crate) fn check_day(
+    day_of_week: DayOfWeek,
+    day: &Day,
+    week: usize,
+    bell_times: &bell_times::Day,
+) -> anyhow::Result<()> {
+    for (period, lesson) in day.lessons() {
+        if bell_times.get(period).is_none() && !lesson.is_free() {
+            anyhow::bail!(
+                "week {} {:?} has a lesson in {} but there are no bell times for it",
+                week_name(week),
+                day_of_week,
+                period
+            );
+        }
     }
 
-    // Set color for recess, lunch and assembly
-    let oklab = tincture::Oklab {
-        l: 0.5,
-        a: 0.0,
-        b: 0.0,
+    Ok(())
+}
+
+/// Pairs the lessons of a single day with its bell times,
+/// leaving out lessons in periods which aren't in them and free periods which are hidden
+pub(crate) fn day_events<'a>(
+    events: &mut Vec<Event<'a>>,
+    day_of_week: DayOfWeek,
+    day: &'a Day,
+    week: usize,
+    bell_times: &bell_times::Day,
+    free_periods: &'a FreePeriods,
+) {
+    // Lunch is one event unless there's a class in either half
+    let whole_lunch = day.get(Period::Lunch1) == Some(&Lesson::Lunch1)
+        && day.get(Period::Lunch2) == Some(&Lesson::Lunch2);
+
+    let not_free = |(_, bell_time): &(usize, &bell_times::BellTime)| {
+        day.get(bell_time.period())
+            .is_some_and(|lesson| !lesson.is_free())
     };
-    let lrgb: tincture::LinearRgb = tincture::convert(oklab);
-    let srgb = tincture::Srgb::from(lrgb);
+    let first = bell_times.bell_times.iter().enumerate().find(not_free);
+    let last = bell_times.bell_times.iter().enumerate().rfind(not_free);
+    let not_free = first.zip(last).map(|((first, _), (last, _))| (first, last));
 
-    let color = format!(
-        "<array><real>{}</real><real>{}</real><real>{}</real></array>",
-        srgb.r, srgb.g, srgb.b
-    );
+    for (idx, bell_time) in bell_times.bell_times.iter().enumerate() {
+        let Some(lesson) = day.get(bell_time.period()) else {
+            continue;
+        };
+        if *lesson == Lesson::Lunch2 && whole_lunch {
+            continue;
+        }
 
-    xml += &format!("<key>Lunch</key>{color}<key>Recess</key>{color}<key>Free Period</key>{color}");
+        let mut study = None;
+        if lesson.is_free() {
+            match free_periods.free_event(bell_times, idx, not_free) {
+                FreeEvent::Hidden => continue,
+                FreeEvent::Free => {}
+                FreeEvent::Study(free_study) => study = Some(free_study),
+            }
+        }
 
-    if let Some((assembly_sc, assembly_cc)) = assembly_subject_class_codes {
-        xml += &format!("<key>Assembly {}.{}</key>{color}", assembly_sc, assembly_cc);
-    }
+        let end = match bell_times.get(Period::Lunch2) {
+            Some(lunch2) if *lesson == Lesson::Lunch1 && whole_lunch => lunch2.end(),
+            _ => bell_time.end(),
+        };
 
-    // Add settings
-    xml += r#"</dict>
-    <key>NumberOfWeeks</key>
-    <integer>2</integer>
-    <key>SelectedWeek</key>
-    <integer>1</integer>
-    <key>SelectedWeekUpdateDate</key>
-    <date>2021-11-10T22:27:02Z</date>
-    <key>WeekendDaysAreActive</key>
-    <false/>
-    </dict>"#;
-
-    xml += "<key>WeekEvents</key><array>";
-    let bell_times = BellTimes::default();
-    for (day_of_week, day) in &t.week_a.days {
-        process_day(&mut xml, day_of_week, day, true, &bell_times);
+        events.push(Event {
+            day_of_week,
+            week,
+            period: bell_time.period(),
+            start: bell_time.start(),
+            end,
+            lesson,
+            study,
+        });
     }
-    for (day_of_week, day) in &t.week_b.days {
-        process_day(&mut xml, day_of_week, day, false, &bell_times);
+}
+
+fn event_dict(event: &Event<'_>, templates: &Templates) -> Value {
+    let day_num = match event.day_of_week {
+        DayOfWeek::Monday => 0,
+        DayOfWeek::Tuesday => 1,
+        DayOfWeek::Wednesday => 2,
+        DayOfWeek::Thursday => 3,
+        DayOfWeek::Friday => 4,
+    };
+
+    let mut dict = Dict::new();
+    dict.insert("dayNum".to_string(), Value::Integer(day_num));
+    dict.insert(
+        "time".to_string(),
+        Value::Real(event.start.num_seconds_from_midnight().into()),
+    );
+    dict.insert(
+        "endTime".to_string(),
+        Value::Real(event.end.num_seconds_from_midnight().into()),
+    );
+    dict.insert("title".to_string(), templates.title(event).into());
+    if let Some(info) = templates.info(event) {
+        dict.insert("info".to_string(), info.into());
     }
-    xml += "</array></dict></plist>";
+    dict.insert("weekNum".to_string(), Value::Integer(event.week as i64));
 
-    xml
+    Value::Dict(dict)
 }
 
-fn gen_color(hue: usize) -> tincture::Srgb {
-    let oklch = tincture::Oklch {
-        l: if (95..=110).contains(&hue) {
-            0.85
-        } else {
-            0.75
-        },
-        c: if (95..=110).contains(&hue) {
-            0.14
-        } else {
-            0.125
-        },
-        h: tincture::Hue::from_degrees(hue as f32).unwrap(),
+#[cfg(test)]
+#[test]
+fn test_colour_keys_from_every_week() {
+    use self::colours::{ColourConfig, ColourRegistry};
+    use crate::scraper::LessonRules;
+    use crate::types::Week;
+
+    // Music and assembly only happen in week B
+    let mut week_a = Week::default();
+    week_a.days.insert(
+        DayOfWeek::Wednesday,
+        Day::from_lessons([(Period::P1, Lesson::class("MAT"))]).unwrap(),
+    );
+    let mut week_b = Week::default();
+    week_b.days.insert(
+        DayOfWeek::Wednesday,
+        Day::from_lessons([
+            (Period::P1, Lesson::class("MUS")),
+            (Period::Assembly, Lesson::assembly()),
+        ])
+        .unwrap(),
+    );
+    let t = Timetable {
+        weeks: vec![week_a, week_b],
+    };
+
+    let mut registry = ColourRegistry::default();
+    registry.register(&t);
+    let palette = registry
+        .palette(&ColourConfig::default(), &LessonRules::default())
+        .unwrap();
+    let selected_week = SelectedWeek {
+        week: 0,
+        updated: Utc::now(),
     };
-    let oklab = tincture::Oklab::from(oklch);
-    let lrgb: tincture::LinearRgb = tincture::convert(oklab);
-    tincture::Srgb::from(lrgb)
+
+    let plist = gen_timetable_plist(
+        &t,
+        &BellTimes::default(),
+        &selected_week,
+        &palette,
+        &Templates::default(),
+        &EventOptions::default(),
+    )
+    .unwrap();
+    let xml = crate::plist::to_xml(&plist);
+    for key in ["MAT.1", "MUS.1", "Assembly PC.1"] {
+        assert!(
+            xml.contains(&format!("<key>{key}</key>")),
+            "{key} has no colour"
+        );
+    }
+
+    // Taking a week B colour out is caught
+    let Value::Dict(mut timetable) = plist else {
+        panic!("timetable isn't a dict")
+    };
+    if let Some(Value::Dict(settings)) = timetable.get_mut("Settings") {
+        if let Some(Value::Dict(colours)) = settings.get_mut("ColorSettings") {
+            colours.remove("MUS.1");
+        }
+    }
+    assert_eq!(
+        check_colour_keys(&timetable).unwrap_err().to_string(),
+        "no colour for MUS.1"
+    );
 }
 
-fn process_day(
-    xml: &mut String,
-    day_of_week: &DayOfWeek,
-    day: &Day,
-    week_a: bool,
-    bell_times: &BellTimes,
-) {
-    for (idx, lesson) in day.lessons.iter().enumerate() {
-        if matches!(*lesson, Lesson::AbsentPeriod | Lesson::Lunch2) {
-            continue;
+#[cfg(test)]
+mod lunch_tests {
+    use super::*;
+    use crate::generator::colours::{ColourConfig, ColourRegistry};
+    use crate::generator::parse::parse_timetable_plist;
+    use crate::scraper::LessonRules;
+    use crate::types::Week;
+
+    /// Monday's events from P4 to P5 with a class or lunch in each half of lunch,
+    /// checking they don't overlap and survive a round trip through a `.timetable` file
+    fn lunch_events(lunch1: Lesson, lunch2: Lesson) -> Vec<String> {
+        let periods = [Period::P4, Period::Lunch1, Period::Lunch2, Period::P5];
+        let lessons = [Lesson::class("MAT"), lunch1, lunch2, Lesson::class("ENG")];
+        let mut week = Week::default();
+        week.days.insert(
+            DayOfWeek::Monday,
+            Day::from_lessons(periods.into_iter().zip(lessons)).unwrap(),
+        );
+        let t = Timetable { weeks: vec![week] };
+
+        let bell_times = BellTimes::default();
+        let options = EventOptions::default();
+        let templates = Templates::default();
+        let events = timetable_events(&t, &bell_times, &options).unwrap();
+        for pair in events.windows(2) {
+            assert!(pair[0].end <= pair[1].start, "{} overlaps", pair[0].period);
         }
-        let day = match day_of_week {
-            DayOfWeek::Monday => &bell_times.monday,
-            DayOfWeek::Tuesday => &bell_times.tuesday,
-            DayOfWeek::Wednesday => &bell_times.wednesday,
-            DayOfWeek::Thursday => &bell_times.thursday,
-            DayOfWeek::Friday => &bell_times.friday,
+
+        let mut registry = ColourRegistry::default();
+        registry.register(&t);
+        let palette = registry
+            .palette(&ColourConfig::default(), &LessonRules::default())
+            .unwrap();
+        let selected_week = SelectedWeek {
+            week: 0,
+            updated: Utc::now(),
         };
-        let bell_times = &day.bell_times;
-
-        *xml += &format!(
-            r#"<dict>
-        <key>dayNum</key>
-        <integer>{}</integer>
-        <key>time</key>
-        <real>{}</real>
-        <key>endTime</key>
-        <real>{}</real>
-        <key>title</key>
-        <string>{}</string>{}
-        <key>weekNum</key>
-        <integer>{}</integer>
-    </dict>"#,
-            match day_of_week {
-                DayOfWeek::Monday => 0,
-                DayOfWeek::Tuesday => 1,
-                DayOfWeek::Wednesday => 2,
-                DayOfWeek::Thursday => 3,
-                DayOfWeek::Friday => 4,
-            },
-            (bell_times[idx].start().hour() * 60 + bell_times[idx].start().minute()) * 60,
-            if lesson == &Lesson::Lunch1 {
-                (bell_times[idx + 1].end().hour() * 60 + bell_times[idx + 1].end().minute()) * 60
-            } else {
-                (bell_times[idx].end().hour() * 60 + bell_times[idx].end().minute()) * 60
-            },
-            match lesson {
-                Lesson::Present {
-                    subject,
-                    subject_code,
-                    class_code,
-                    ..
-                } if subject.name == "Assembly" => format!("Assembly {subject_code}.{class_code}"),
-                Lesson::Present {
-                    subject_code,
-                    class_code,
-                    ..
-                } => format!("{subject_code}.{class_code}"),
-                Lesson::FreePeriod => "Free Period".to_string(),
-                Lesson::Recess => "Recess".to_string(),
-                Lesson::Lunch1 => "Lunch".to_string(),
-                Lesson::AbsentPeriod | Lesson::Lunch2 => unreachable!(),
-            },
-            match lesson {
-                Lesson::Present {
-                    room: Some(room), ..
-                } => format!("<key>info</key><string>{}</string>", room),
-                _ => String::new(),
-            },
-            if week_a { 0 } else { 1 }
+        let plist = gen_timetable_plist(
+            &t,
+            &bell_times,
+            &selected_week,
+            &palette,
+            &templates,
+            &options,
+        )
+        .unwrap();
+        let parsed = parse_timetable_plist(
+            &plist,
+            &bell_times,
+            &LessonRules::default(),
+            &templates,
+            &options.free_periods,
+        )
+        .unwrap();
+        let day = &parsed.timetable.weeks[0].days[&DayOfWeek::Monday];
+        for period in periods {
+            assert_eq!(
+                day.get(period),
+                t.weeks[0].days[&DayOfWeek::Monday].get(period)
+            );
+        }
+
+        events
+            .iter()
+            .map(|event| {
+                format!(
+                    "{}-{} {}",
+                    event.start.format("%H:%M"),
+                    event.end.format("%H:%M"),
+                    templates.title(event)
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_lunch_and_lunch() {
+        assert_eq!(
+            lunch_events(Lesson::Lunch1, Lesson::Lunch2),
+            [
+                "12:00-12:55 MAT.1",
+                "12:55-13:35 Lunch",
+                "13:35-14:30 ENG.1"
+            ]
         );
     }
-}
 
-#[cfg(test)]
-#[test]
-fn test_all_colours() {
-    use tincture::ColorSpace;
-    for hue in 0..=360 {
-        assert!(gen_color(hue).in_bounds());
+    #[test]
+    fn test_class_and_lunch() {
+        assert_eq!(
+            lunch_events(Lesson::class("MUS"), Lesson::Lunch2),
+            [
+                "12:00-12:55 MAT.1",
+                "12:55-13:15 MUS.1",
+                "13:15-13:35 Lunch",
+                "13:35-14:30 ENG.1"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lunch_and_class() {
+        assert_eq!(
+            lunch_events(Lesson::Lunch1, Lesson::class("MUS")),
+            [
+                "12:00-12:55 MAT.1",
+                "12:55-13:15 Lunch",
+                "13:15-13:35 MUS.1",
+                "13:35-14:30 ENG.1"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_class_and_class() {
+        assert_eq!(
+            lunch_events(Lesson::class("MUS"), Lesson::class("ART")),
+            [
+                "12:00-12:55 MAT.1",
+                "12:55-13:15 MUS.1",
+                "13:15-13:35 ART.1",
+                "13:35-14:30 ENG.1"
+            ]
+        );
     }
 }
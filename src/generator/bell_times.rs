@@ -1,5 +1,7 @@
+use crate::types::{DayOfWeek, Period};
 use chrono::{Duration, NaiveTime};
-use itertools::Itertools;
+use serde::{Deserialize, Deserializer};
+use std::path::Path;
 
 pub(crate) struct BellTimes {
     pub(crate) monday: Day,
@@ -9,7 +11,65 @@ pub(crate) struct BellTimes {
     pub(crate) friday: Day,
 }
 
+impl BellTimes {
+    /// Loads bell times from a TOML or JSON file, depending on its extension
+    ///
+    /// ```toml
+    /// # Used for every day without its own list
+    /// [[default]]
+    /// period = "P0"
+    /// start = "07:55"
+    /// end = "08:55"
+    ///
+    /// [[wednesday]]
+    /// ...
+    /// ```
+    pub(crate) fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("failed to read {}: {}", path.display(), e))?;
+
+        let file: BellTimesFile = match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => serde_json::from_str(&text)
+                .map_err(|e| anyhow::anyhow!("failed to parse {}: {}", path.display(), e))?,
+            _ => toml::from_str(&text)
+                .map_err(|e| anyhow::anyhow!("failed to parse {}: {}", path.display(), e))?,
+        };
+
+        let day = |day_of_week: DayOfWeek, periods: &Option<Vec<PeriodTimes>>| {
+            let periods = periods
+                .as_ref()
+                .or(file.default.as_ref())
+                .ok_or_else(|| anyhow::anyhow!("no bell times for {:?}", day_of_week))?;
+
+            Day::from_periods(periods)
+                .map_err(|e| anyhow::anyhow!("invalid bell times for {:?}: {}", day_of_week, e))
+        };
+
+        Ok(Self {
+            monday: day(DayOfWeek::Monday, &file.monday)?,
+            tuesday: day(DayOfWeek::Tuesday, &file.tuesday)?,
+            wednesday: day(DayOfWeek::Wednesday, &file.wednesday)?,
+            thursday: day(DayOfWeek::Thursday, &file.thursday)?,
+            friday: day(DayOfWeek::Friday, &file.friday)?,
+        })
+    }
+
+    pub(crate) fn day(&self, day_of_week: DayOfWeek) -> &Day {
+        match day_of_week {
+            DayOfWeek::Monday => &self.monday,
+            DayOfWeek::Tuesday => &self.tuesday,
+            DayOfWeek::Wednesday => &self.wednesday,
+            DayOfWeek::Thursday => &self.thursday,
+            DayOfWeek::Friday => &self.friday,
+        }
+    }
+}
+
 impl BellTime {
+    pub(crate) fn period(&self) -> Period {
+        self.period
+    }
+
     pub(crate) fn start(&self) -> NaiveTime {
         self.start
     }
@@ -22,48 +82,52 @@ impl BellTime {
 impl Default for BellTimes {
     fn default() -> Self {
         let not_wednesday = &[
-            NaiveTime::from_hms_opt(7, 55, 0).unwrap(),  // 0
-            NaiveTime::from_hms_opt(8, 55, 0).unwrap(),  // 1
-            NaiveTime::from_hms_opt(9, 50, 0).unwrap(),  // 2
-            NaiveTime::from_hms_opt(10, 45, 0).unwrap(), // R
-            NaiveTime::from_hms_opt(11, 5, 0).unwrap(),  // 3
-            NaiveTime::from_hms_opt(12, 0, 0).unwrap(),  // 4
-            NaiveTime::from_hms_opt(12, 55, 0).unwrap(), // L1
-            NaiveTime::from_hms_opt(13, 15, 0).unwrap(), // L2
-            NaiveTime::from_hms_opt(13, 35, 0).unwrap(), // 5
-            NaiveTime::from_hms_opt(14, 30, 0).unwrap(), // 6
-            NaiveTime::from_hms_opt(15, 25, 0).unwrap(), // 7
-            NaiveTime::from_hms_opt(16, 20, 0).unwrap(), // 8
-            NaiveTime::from_hms_opt(17, 15, 0).unwrap(), // End of 8
+            (Period::P0, NaiveTime::from_hms_opt(7, 55, 0).unwrap()),
+            (Period::P1, NaiveTime::from_hms_opt(8, 55, 0).unwrap()),
+            (Period::P2, NaiveTime::from_hms_opt(9, 50, 0).unwrap()),
+            (Period::Recess, NaiveTime::from_hms_opt(10, 45, 0).unwrap()),
+            (Period::P3, NaiveTime::from_hms_opt(11, 5, 0).unwrap()),
+            (Period::P4, NaiveTime::from_hms_opt(12, 0, 0).unwrap()),
+            (Period::Lunch1, NaiveTime::from_hms_opt(12, 55, 0).unwrap()),
+            (Period::Lunch2, NaiveTime::from_hms_opt(13, 15, 0).unwrap()),
+            (Period::P5, NaiveTime::from_hms_opt(13, 35, 0).unwrap()),
+            (Period::P6, NaiveTime::from_hms_opt(14, 30, 0).unwrap()),
+            (Period::P7, NaiveTime::from_hms_opt(15, 25, 0).unwrap()),
+            (Period::P8, NaiveTime::from_hms_opt(16, 20, 0).unwrap()),
         ];
+        let not_wednesday_end = NaiveTime::from_hms_opt(17, 15, 0).unwrap();
 
         let wednesday = &[
-            NaiveTime::from_hms_opt(7, 55, 0).unwrap(),  // 0
-            NaiveTime::from_hms_opt(8, 55, 0).unwrap(),  // 1
-            NaiveTime::from_hms_opt(9, 50, 0).unwrap(),  // 2
-            NaiveTime::from_hms_opt(10, 45, 0).unwrap(), // A
-            NaiveTime::from_hms_opt(11, 5, 0).unwrap(),  // R
-            NaiveTime::from_hms_opt(11, 25, 0).unwrap(), // 3
-            NaiveTime::from_hms_opt(12, 20, 0).unwrap(), // L1
-            NaiveTime::from_hms_opt(12, 40, 0).unwrap(), // L2
-            NaiveTime::from_hms_opt(13, 0, 0).unwrap(),  // 4
-            NaiveTime::from_hms_opt(13, 55, 0).unwrap(), // 5
-            NaiveTime::from_hms_opt(14, 50, 0).unwrap(), // 6
-            NaiveTime::from_hms_opt(15, 45, 0).unwrap(), // 7
-            NaiveTime::from_hms_opt(16, 40, 0).unwrap(), // End of 7
+            (Period::P0, NaiveTime::from_hms_opt(7, 55, 0).unwrap()),
+            (Period::P1, NaiveTime::from_hms_opt(8, 55, 0).unwrap()),
+            (Period::P2, NaiveTime::from_hms_opt(9, 50, 0).unwrap()),
+            (
+                Period::Assembly,
+                NaiveTime::from_hms_opt(10, 45, 0).unwrap(),
+            ),
+            (Period::Recess, NaiveTime::from_hms_opt(11, 5, 0).unwrap()),
+            (Period::P3, NaiveTime::from_hms_opt(11, 25, 0).unwrap()),
+            (Period::Lunch1, NaiveTime::from_hms_opt(12, 20, 0).unwrap()),
+            (Period::Lunch2, NaiveTime::from_hms_opt(12, 40, 0).unwrap()),
+            (Period::P4, NaiveTime::from_hms_opt(13, 0, 0).unwrap()),
+            (Period::P5, NaiveTime::from_hms_opt(13, 55, 0).unwrap()),
+            (Period::P6, NaiveTime::from_hms_opt(14, 50, 0).unwrap()),
+            (Period::P7, NaiveTime::from_hms_opt(15, 45, 0).unwrap()),
         ];
+        let wednesday_end = NaiveTime::from_hms_opt(16, 40, 0).unwrap();
 
         Self {
-            monday: Day::new(not_wednesday),
-            tuesday: Day::new(not_wednesday),
-            wednesday: Day::new(wednesday),
-            thursday: Day::new(not_wednesday),
-            friday: Day::new(not_wednesday),
+            monday: Day::new(not_wednesday, not_wednesday_end),
+            tuesday: Day::new(not_wednesday, not_wednesday_end),
+            wednesday: Day::new(wednesday, wednesday_end),
+            thursday: Day::new(not_wednesday, not_wednesday_end),
+            friday: Day::new(not_wednesday, not_wednesday_end),
         }
     }
 }
 
 pub(crate) struct BellTime {
+    period: Period,
     start: NaiveTime,
     length: Duration,
 }
@@ -73,16 +137,150 @@ pub(crate) struct Day {
 }
 
 impl Day {
-    fn new(times: &[NaiveTime]) -> Self {
-        let mut bell_times = Vec::with_capacity(times.len());
+    /// Builds back to back periods, each running until the next one starts
+    fn new(periods: &[(Period, NaiveTime)], end: NaiveTime) -> Self {
+        let mut bell_times = Vec::with_capacity(periods.len());
 
-        for (start, end) in times.iter().cloned().tuple_windows() {
+        for (idx, (period, start)) in periods.iter().enumerate() {
+            let end = periods
+                .get(idx + 1)
+                .map_or(end, |(_, next_start)| *next_start);
             bell_times.push(BellTime {
-                start,
-                length: end - start,
+                period: *period,
+                start: *start,
+                length: end - *start,
             });
         }
 
         Self { bell_times }
     }
+
+    /// Builds a day from a config file,
+    /// which must list periods in order without any gaps or overlaps between them
+    pub(crate) fn from_periods(periods: &[PeriodTimes]) -> anyhow::Result<Self> {
+        anyhow::ensure!(!periods.is_empty(), "no periods");
+
+        for (idx, times) in periods.iter().enumerate() {
+            anyhow::ensure!(
+                times.end > times.start,
+                "{} ends at {} before it starts at {}",
+                times.period,
+                times.end.format("%H:%M"),
+                times.start.format("%H:%M")
+            );
+
+            if periods[..idx]
+                .iter()
+                .any(|other| other.period == times.period)
+            {
+                anyhow::bail!("{} is listed more than once", times.period);
+            }
+
+            if let Some(next) = periods.get(idx + 1) {
+                anyhow::ensure!(
+                    next.start >= times.end,
+                    "{} starts at {} and overlaps {}, which ends at {}",
+                    next.period,
+                    next.start.format("%H:%M"),
+                    times.period,
+                    times.end.format("%H:%M")
+                );
+                anyhow::ensure!(
+                    next.start <= times.end,
+                    "there is a gap between {} ending at {} and {} starting at {}",
+                    times.period,
+                    times.end.format("%H:%M"),
+                    next.period,
+                    next.start.format("%H:%M")
+                );
+            }
+        }
+
+        let bell_times = periods
+            .iter()
+            .map(|times| BellTime {
+                period: times.period,
+                start: times.start,
+                length: times.end - times.start,
+            })
+            .collect();
+
+        Ok(Self { bell_times })
+    }
+
+    pub(crate) fn get(&self, period: Period) -> Option<&BellTime> {
+        self.bell_times
+            .iter()
+            .find(|bell_time| bell_time.period == period)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BellTimesFile {
+    default: Option<Vec<PeriodTimes>>,
+    monday: Option<Vec<PeriodTimes>>,
+    tuesday: Option<Vec<PeriodTimes>>,
+    wednesday: Option<Vec<PeriodTimes>>,
+    thursday: Option<Vec<PeriodTimes>>,
+    friday: Option<Vec<PeriodTimes>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct PeriodTimes {
+    period: Period,
+    #[serde(deserialize_with = "deserialize_time")]
+    start: NaiveTime,
+    #[serde(deserialize_with = "deserialize_time")]
+    end: NaiveTime,
+}
+
+/// Reads times written like "08:55"
+pub(crate) fn deserialize_time<'de, D>(deserializer: D) -> Result<NaiveTime, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let text = String::deserialize(deserializer)?;
+    NaiveTime::parse_from_str(&text, "%H:%M").map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+#[test]
+fn test_gaps_and_overlaps() {
+    let period = |period: Period, start: (u32, u32), end: (u32, u32)| PeriodTimes {
+        period,
+        start: NaiveTime::from_hms_opt(start.0, start.1, 0).unwrap(),
+        end: NaiveTime::from_hms_opt(end.0, end.1, 0).unwrap(),
+    };
+
+    assert!(Day::from_periods(&[
+        period(Period::P1, (8, 55), (9, 50)),
+        period(Period::P2, (9, 50), (10, 45)),
+    ])
+    .is_ok());
+
+    // Overlap
+    assert!(Day::from_periods(&[
+        period(Period::P1, (8, 55), (9, 50)),
+        period(Period::P2, (9, 45), (10, 45)),
+    ])
+    .is_err());
+
+    // Gap
+    assert!(Day::from_periods(&[
+        period(Period::P1, (8, 55), (9, 50)),
+        period(Period::P2, (9, 55), (10, 45)),
+    ])
+    .is_err());
+
+    // Ends before it starts
+    assert!(Day::from_periods(&[period(Period::P1, (9, 50), (8, 55))]).is_err());
+
+    // Duplicate name
+    assert!(Day::from_periods(&[
+        period(Period::P1, (8, 55), (9, 50)),
+        period(Period::P1, (9, 50), (10, 45)),
+    ])
+    .is_err());
 }
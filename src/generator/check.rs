@@ -0,0 +1,245 @@
+use super::bell_times::{self, BellTimes};
+use super::colours::Palette;
+use super::template::Templates;
+use super::{timetable_events, Event, EventOptions};
+use crate::calendar::{DatedEvent, TermCalendar};
+use crate::types::{week_name, DayOfWeek, Timetable};
+use chrono::NaiveDate;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Looks for events which no exporter should write: ones which end before they start,
+/// overlap another event, fall outside the day's bell times or have no colour
+pub(crate) fn check_events(
+    events: &[Event<'_>],
+    bell_times: &BellTimes,
+    palette: &Palette,
+    templates: &Templates,
+) -> Vec<String> {
+    let mut days: BTreeMap<(usize, DayOfWeek), Vec<&Event<'_>>> = BTreeMap::new();
+    for event in events {
+        days.entry((event.week, event.day_of_week))
+            .or_default()
+            .push(event);
+    }
+
+    let mut problems = Vec::new();
+    for ((week, day_of_week), day) in days {
+        let name = format!("Week {} {:?}", week_name(week), day_of_week);
+        check_day(
+            &mut problems,
+            &name,
+            day,
+            bell_times.day(day_of_week),
+            templates,
+        );
+    }
+    check_colours(&mut problems, events.iter(), palette, templates);
+
+    problems
+}
+
+/// Like [`check_events`], for lessons on particular dates,
+/// which follow the bell times of any special day they're on
+fn check_dated_events(
+    events: &[DatedEvent<'_>],
+    calendar: &TermCalendar,
+    bell_times: &BellTimes,
+    palette: &Palette,
+    templates: &Templates,
+) -> anyhow::Result<Vec<String>> {
+    let mut days: BTreeMap<NaiveDate, Vec<&Event<'_>>> = BTreeMap::new();
+    for dated_event in events {
+        days.entry(dated_event.date)
+            .or_default()
+            .push(&dated_event.event);
+    }
+
+    let mut problems = Vec::new();
+    for (date, day) in days {
+        let special_bell_times = calendar.special_bell_times(date)?;
+        let day_bell_times = match &special_bell_times {
+            Some(special_bell_times) => special_bell_times,
+            None => bell_times.day(day[0].day_of_week),
+        };
+        check_day(
+            &mut problems,
+            &date.to_string(),
+            day,
+            day_bell_times,
+            templates,
+        );
+    }
+
+    // Lessons taken over by a special day are grey
+    let lessons = events
+        .iter()
+        .filter(|dated_event| dated_event.replaced_by.is_none())
+        .map(|dated_event| &dated_event.event);
+    check_colours(&mut problems, lessons, palette, templates);
+
+    Ok(problems)
+}
+
+/// Checks a timetable's events,
+/// along with its lessons on every date of the calendar when there is one
+pub(crate) fn check_timetable(
+    t: &Timetable,
+    bell_times: &BellTimes,
+    calendar: Option<&TermCalendar>,
+    palette: &Palette,
+    templates: &Templates,
+    options: &EventOptions,
+) -> anyhow::Result<Vec<String>> {
+    let events = timetable_events(t, bell_times, options)?;
+    let mut problems = check_events(&events, bell_times, palette, templates);
+
+    if let Some(calendar) = calendar {
+        let (from, to) = calendar.range();
+        let dated_events = calendar.dated_events(t, bell_times, options, from, to)?;
+        for problem in check_dated_events(&dated_events, calendar, bell_times, palette, templates)?
+        {
+            if !problems.contains(&problem) {
+                problems.push(problem);
+            }
+        }
+    }
+
+    Ok(problems)
+}
+
+/// Checks the events of one day against each other and its bell times
+fn check_day(
+    problems: &mut Vec<String>,
+    name: &str,
+    mut day: Vec<&Event<'_>>,
+    bell_times: &bell_times::Day,
+    templates: &Templates,
+) {
+    let describe = |event: &Event<'_>| {
+        format!(
+            "{} {} ({}-{})",
+            name,
+            templates.title(event),
+            event.start.format("%H:%M"),
+            event.end.format("%H:%M")
+        )
+    };
+
+    let bounds = bell_times
+        .bell_times
+        .first()
+        .zip(bell_times.bell_times.last());
+    for event in &day {
+        if event.end <= event.start {
+            problems.push(format!("{} doesn't end after it starts", describe(event)));
+        }
+
+        let inside = bounds
+            .is_some_and(|(first, last)| first.start() <= event.start && event.end <= last.end());
+        if !inside {
+            problems.push(format!("{} is outside the bell times", describe(event)));
+        }
+    }
+
+    // Compared with whichever event before it ends last,
+    // so one event inside a longer one is still caught
+    day.retain(|event| event.end > event.start);
+    day.sort_by_key(|event| event.start);
+    let mut latest: Option<&Event<'_>> = None;
+    for event in day {
+        match latest {
+            Some(earlier) if event.start < earlier.end => {
+                problems.push(format!(
+                    "{} overlaps {}",
+                    describe(earlier),
+                    describe(event)
+                ));
+                if event.end > earlier.end {
+                    latest = Some(event);
+                }
+            }
+            _ => latest = Some(event),
+        }
+    }
+}
+
+fn check_colours<'a>(
+    problems: &mut Vec<String>,
+    events: impl Iterator<Item = &'a Event<'a>>,
+    palette: &Palette,
+    templates: &Templates,
+) {
+    let uncoloured: BTreeSet<_> = events
+        .filter(|event| palette.event_colour(event).is_err())
+        .map(|event| templates.title(event))
+        .collect();
+    for title in uncoloured {
+        problems.push(format!("{title} has no colour"));
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_check_events() {
+    use super::colours::{ColourConfig, ColourRegistry};
+    use crate::scraper::LessonRules;
+    use crate::types::{Day, Lesson, Period, Timetable, Week};
+    use chrono::NaiveTime;
+
+    // Only maths has a colour
+    let mut week = Week::default();
+    week.days.insert(
+        DayOfWeek::Monday,
+        Day::from_lessons([(Period::P1, Lesson::class("MAT"))]).unwrap(),
+    );
+    let mut registry = ColourRegistry::default();
+    registry.register(&Timetable { weeks: vec![week] });
+    let palette = registry
+        .palette(&ColourConfig::default(), &LessonRules::default())
+        .unwrap();
+
+    let maths = Lesson::class("MAT");
+    let english = Lesson::class("ENG");
+    let time = |hour, minute| NaiveTime::from_hms_opt(hour, minute, 0).unwrap();
+    let event = |lesson, start, end| Event {
+        day_of_week: DayOfWeek::Monday,
+        week: 0,
+        period: Period::P1,
+        start,
+        end,
+        lesson,
+        study: None,
+    };
+
+    let check = |events: &[Event<'_>]| {
+        check_events(
+            events,
+            &BellTimes::default(),
+            &palette,
+            &Templates::default(),
+        )
+    };
+
+    assert!(check(&[
+        event(&maths, time(8, 55), time(9, 50)),
+        event(&maths, time(9, 50), time(10, 45)),
+    ])
+    .is_empty());
+
+    assert_eq!(
+        check(&[
+            event(&maths, time(9, 50), time(8, 55)),
+            event(&maths, time(17, 0), time(18, 0)),
+            event(&maths, time(9, 0), time(10, 0)),
+            event(&english, time(9, 30), time(9, 40)),
+            event(&english, time(9, 45), time(9, 55)),
+        ]),
+        [
+            "Week A Monday MAT.1 (09:50-08:55) doesn't end after it starts",
+            "Week A Monday MAT.1 (17:00-18:00) is outside the bell times",
+            "Week A Monday MAT.1 (09:00-10:00) overlaps Week A Monday ENG.1 (09:30-09:40)",
+            "Week A Monday MAT.1 (09:00-10:00) overlaps Week A Monday ENG.1 (09:45-09:55)",
+            "ENG.1 has no colour"
+        ]
+    );
+}
@@ -0,0 +1,377 @@
+use self::palette::{gen_palette, in_gamut, to_srgb, PaletteOptions};
+use super::template::Templates;
+use super::Event;
+use crate::scraper::{LessonColour, LessonRules};
+use crate::types::{Lesson, Timetable};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use tincture::{Hex, Oklab, Oklch, Srgb};
+
+mod palette;
+
+/// `[colours]` in the config file
+///
+/// ```toml
+/// [colours]
+/// theme = "pastel"
+///
+/// [colours.pinned]
+/// MAT = "#f27166"
+///
+/// # Hue in degrees
+/// [colours.faculties]
+/// Science = 150
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ColourConfig {
+    /// Keep subject colours apart for people with red-green colour blindness
+    #[serde(default)]
+    colour_blind: bool,
+    #[serde(default)]
+    theme: Theme,
+    /// Colours for particular subject codes
+    #[serde(default)]
+    pinned: BTreeMap<String, String>,
+    /// Hues for faculties, where each subject in the faculty gets its own lightness of the hue
+    #[serde(default)]
+    faculties: BTreeMap<String, f32>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Theme {
+    #[default]
+    Standard,
+    Pastel,
+    Vivid,
+    HighContrast,
+    /// Darker colours which aren't glaring on a dark background
+    Dark,
+}
+
+impl Theme {
+    fn palette_options(self, colour_blind: bool) -> PaletteOptions {
+        let (lightness, chroma) = match self {
+            Theme::Standard => ((0.7, 0.85), (0.08, 0.16)),
+            Theme::Pastel => ((0.82, 0.92), (0.04, 0.09)),
+            Theme::Vivid => ((0.62, 0.78), (0.14, 0.22)),
+            Theme::HighContrast => ((0.45, 0.9), (0.12, 0.2)),
+            Theme::Dark => ((0.45, 0.6), (0.08, 0.14)),
+        };
+
+        PaletteOptions {
+            lightness,
+            chroma,
+            colour_blind,
+        }
+    }
+
+    /// Lightness of the grey used for breaks and free periods
+    fn neutral_lightness(self) -> f32 {
+        match self {
+            Theme::Standard | Theme::Vivid => 0.5,
+            Theme::Pastel => 0.75,
+            Theme::HighContrast | Theme::Dark => 0.35,
+        }
+    }
+}
+
+/// Colours for every subject in the registry, and for breaks and free periods
+pub(crate) struct Palette {
+    subjects: HashMap<String, Srgb>,
+    neutral: Srgb,
+    /// Which lessons are neutral instead of their subject's colour
+    special_lessons: LessonRules,
+}
+
+impl Palette {
+    pub(crate) fn subject(&self, subject_code: &str) -> Option<Srgb> {
+        self.subjects.get(subject_code).copied()
+    }
+
+    pub(crate) fn neutral(&self) -> Srgb {
+        self.neutral
+    }
+
+    /// Colour of an event as shown by any exporter
+    pub(crate) fn event_colour(&self, event: &Event<'_>) -> anyhow::Result<Srgb> {
+        match event.lesson {
+            Lesson::Present {
+                subject,
+                subject_code,
+                ..
+            } if self.special_lessons.treatment(subject, subject_code).colour
+                == LessonColour::Subject =>
+            {
+                self.subject(subject_code)
+                    .ok_or_else(|| anyhow::anyhow!("{} has no colour", subject_code))
+            }
+            _ => Ok(self.neutral),
+        }
+    }
+}
+
+/// Colour of every event title, which is what exporters key colours by.
+/// Fails if a title template gives events with different colours the same title.
+pub(crate) fn event_colours(
+    events: &[Event<'_>],
+    palette: &Palette,
+    templates: &Templates,
+) -> anyhow::Result<BTreeMap<String, Srgb>> {
+    let mut colours = BTreeMap::new();
+    for event in events {
+        let title = templates.title(event);
+        let colour = palette.event_colour(event)?;
+        match colours.insert(title.clone(), colour) {
+            Some(other) if other != colour => anyhow::bail!(
+                "{:?} is the title of events with different colours, \
+                 the title template needs to tell them apart",
+                title
+            ),
+            _ => {}
+        }
+    }
+    Ok(colours)
+}
+
+/// Formats a colour like `#f27166`
+pub(crate) fn hex(colour: Srgb) -> String {
+    format!("#{:06x}", colour.hex())
+}
+
+fn parse_hex(text: &str) -> anyhow::Result<Srgb> {
+    let digits = text
+        .strip_prefix('#')
+        .filter(|digits| digits.len() == 6)
+        .ok_or_else(|| anyhow::anyhow!("{:?} isn't a colour like #f27166", text))?;
+    let hex = u32::from_str_radix(digits, 16)
+        .map_err(|_| anyhow::anyhow!("{:?} isn't a colour like #f27166", text))?;
+
+    Ok(from_hex(hex))
+}
+
+/// Turns a colour like `0xf27166` into its components
+pub(crate) fn from_hex(hex: u32) -> Srgb {
+    let component = |shift: u32| ((hex >> shift) & 0xff) as f32 / 255.0;
+    Srgb {
+        r: component(16),
+        g: component(8),
+        b: component(0),
+    }
+}
+
+/// Every subject code seen across the school, in the order they were first seen.
+///
+/// A subject's colour comes from its position here instead of from one student's subjects,
+/// so it's the same for everyone and doesn't change when new subjects turn up.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct ColourRegistry {
+    subject_codes: Vec<String>,
+    /// Faculty of each subject code
+    #[serde(default)]
+    faculties: BTreeMap<String, String>,
+}
+
+impl ColourRegistry {
+    /// Adds any subject codes which haven't been seen before, returning whether there were any.
+    /// Subjects shown in the neutral colour are registered too, so a special lesson rule
+    /// can give them their own colour without another scrape.
+    pub(crate) fn register(&mut self, t: &Timetable) -> bool {
+        let mut new_subjects = BTreeMap::new();
+
+        for (_, lesson) in t
+            .weeks
+            .iter()
+            .flat_map(|week| week.days.values())
+            .flat_map(|day| day.lessons())
+        {
+            if let Lesson::Present {
+                subject,
+                subject_code,
+                ..
+            } = lesson
+            {
+                if !self.faculties.contains_key(subject_code) {
+                    new_subjects.insert(subject_code.clone(), subject.faculty.clone());
+                }
+            }
+        }
+
+        let changed = !new_subjects.is_empty();
+        for (subject_code, faculty) in new_subjects {
+            if !self.subject_codes.contains(&subject_code) {
+                self.subject_codes.push(subject_code.clone());
+            }
+            self.faculties.insert(subject_code, faculty);
+        }
+        changed
+    }
+
+    /// Resolves the colour of every subject.
+    ///
+    /// Every subject gets the next colour of a palette kept away from grey, in registry order,
+    /// so new subjects never change anyone else's colour. Faculty hues and then pinned colours
+    /// are laid over the top of it.
+    pub(crate) fn palette(
+        &self,
+        config: &ColourConfig,
+        special_lessons: &LessonRules,
+    ) -> anyhow::Result<Palette> {
+        let neutral = Oklab {
+            l: config.theme.neutral_lightness(),
+            a: 0.0,
+            b: 0.0,
+        };
+        let options = config.theme.palette_options(config.colour_blind);
+
+        let colours = gen_palette(self.subject_codes.len(), &options, &[neutral]);
+        let mut subjects: HashMap<_, _> = self.subject_codes.iter().cloned().zip(colours).collect();
+
+        for (faculty, hue) in &config.faculties {
+            let hue = tincture::Hue::from_degrees(*hue)
+                .ok_or_else(|| anyhow::anyhow!("hue for {} must be between 0 and 360", faculty))?;
+
+            let faculty_subject_codes = self
+                .subject_codes
+                .iter()
+                .filter(|subject_code| self.faculties.get(*subject_code) == Some(faculty))
+                .filter(|subject_code| !config.pinned.contains_key(*subject_code));
+            for (idx, subject_code) in faculty_subject_codes.enumerate() {
+                let colour = faculty_colour(hue, idx, &options);
+                subjects.insert(subject_code.clone(), to_srgb(colour));
+            }
+        }
+
+        for (subject_code, colour) in &config.pinned {
+            let colour = parse_hex(colour)
+                .map_err(|e| anyhow::anyhow!("invalid colour for {}: {}", subject_code, e))?;
+            subjects.insert(subject_code.clone(), colour);
+        }
+
+        Ok(Palette {
+            subjects,
+            neutral: to_srgb(neutral),
+            special_lessons: special_lessons.clone(),
+        })
+    }
+}
+
+/// Steps through a few lightnesses of a hue, using as much chroma as the theme allows
+/// while staying in gamut
+fn faculty_colour(hue: tincture::Hue, idx: usize, options: &PaletteOptions) -> Oklab {
+    const STEPS: usize = 4;
+
+    let (lightest, darkest) = (options.lightness.1, options.lightness.0);
+    let l = lightest - (lightest - darkest) * (idx % STEPS) as f32 / (STEPS - 1) as f32;
+
+    let mut c = options.chroma.1;
+    loop {
+        let colour = Oklab::from(Oklch { l, c, h: hue });
+        if in_gamut(colour) || c <= 0.0 {
+            return colour;
+        }
+        c = (c - 0.01).max(0.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Day, DayOfWeek, Period, Week};
+
+    fn timetable(subjects: &[(&str, &str)]) -> Timetable {
+        let lessons = subjects
+            .iter()
+            .zip([Period::P1, Period::P2, Period::P3])
+            .map(|((subject_code, faculty), period)| {
+                (period, Lesson::class(subject_code).in_faculty(faculty))
+            });
+
+        let mut week = Week::default();
+        week.days
+            .insert(DayOfWeek::Monday, Day::from_lessons(lessons).unwrap());
+        Timetable { weeks: vec![week] }
+    }
+
+    #[test]
+    fn test_colours_are_stable() {
+        let mut registry = ColourRegistry::default();
+        assert!(registry.register(&timetable(&[("MAT", "Maths"), ("ENG", "English")])));
+        let palette = registry
+            .palette(&ColourConfig::default(), &LessonRules::default())
+            .unwrap();
+        let maths = palette.subject("MAT").unwrap();
+        let english = palette.subject("ENG").unwrap();
+
+        // A friend with an extra subject doesn't shift anyone else's colours
+        assert!(registry.register(&timetable(&[
+            ("COM", "HSIE"),
+            ("MAT", "Maths"),
+            ("ENG", "English")
+        ])));
+        let palette = registry
+            .palette(&ColourConfig::default(), &LessonRules::default())
+            .unwrap();
+        assert_eq!(palette.subject("MAT").unwrap(), maths);
+        assert_eq!(palette.subject("ENG").unwrap(), english);
+        assert!(!registry.register(&timetable(&[("ENG", "English")])));
+    }
+
+    #[test]
+    fn test_faculty_subjects_dont_shift_colours() {
+        let config: ColourConfig = toml::from_str(
+            r#"
+            [faculties]
+            Science = 150
+            "#,
+        )
+        .unwrap();
+
+        let mut registry = ColourRegistry::default();
+        registry.register(&timetable(&[("MAT", "Maths"), ("PHY", "Science")]));
+        let palette = registry.palette(&config, &LessonRules::default()).unwrap();
+        let (maths, physics) = (palette.subject("MAT"), palette.subject("PHY"));
+
+        registry.register(&timetable(&[("CHE", "Science")]));
+        let palette = registry.palette(&config, &LessonRules::default()).unwrap();
+        assert_eq!(palette.subject("MAT"), maths);
+        assert_eq!(palette.subject("PHY"), physics);
+    }
+
+    #[test]
+    fn test_pinned_and_faculty_colours() {
+        let mut registry = ColourRegistry::default();
+        registry.register(&timetable(&[
+            ("PHY", "Science"),
+            ("CHE", "Science"),
+            ("MAT", "Maths"),
+        ]));
+
+        let config: ColourConfig = toml::from_str(
+            r##"
+            theme = "vivid"
+
+            [pinned]
+            MAT = "#f27166"
+
+            [faculties]
+            Science = 150
+            "##,
+        )
+        .unwrap();
+        let palette = registry.palette(&config, &LessonRules::default()).unwrap();
+
+        assert_eq!(hex(palette.subject("MAT").unwrap()), "#f27166");
+
+        // Same hue with different lightnesses
+        let oklch = |subject_code| {
+            let lrgb = tincture::LinearRgb::from(palette.subject(subject_code).unwrap());
+            Oklch::from(tincture::convert::<_, Oklab>(lrgb))
+        };
+        let (physics, chemistry) = (oklch("PHY"), oklch("CHE"));
+        assert!((physics.h.to_degrees() - 150.0).abs() < 1.0);
+        assert!((chemistry.h.to_degrees() - 150.0).abs() < 1.0);
+        assert!((physics.l - chemistry.l).abs() > 0.02);
+    }
+}
@@ -0,0 +1,153 @@
+use tincture::{LinearRgb, Oklab, Oklch, Srgb};
+
+/// Where in Oklch palette colours are picked from
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct PaletteOptions {
+    pub(crate) lightness: (f32, f32),
+    pub(crate) chroma: (f32, f32),
+    /// Also keep colours apart as they'd look with deuteranopia or protanopia
+    pub(crate) colour_blind: bool,
+}
+
+/// Picks `len` colours, each as far as possible (by ΔE in Oklab) from `avoid` and the colours
+/// picked before it. Earlier colours don't depend on `len`, so growing a palette never
+/// changes the colours already in it.
+pub(crate) fn gen_palette(len: usize, options: &PaletteOptions, avoid: &[Oklab]) -> Vec<Srgb> {
+    let candidates = candidates(options);
+    if candidates.is_empty() {
+        return Vec::new();
+    }
+
+    let views = |colour: Oklab| {
+        let mut views = vec![colour];
+        if options.colour_blind {
+            views.push(simulate(colour, &DEUTERANOPIA));
+            views.push(simulate(colour, &PROTANOPIA));
+        }
+        views
+    };
+    let candidate_views: Vec<_> = candidates.iter().map(|c| views(*c)).collect();
+
+    // Closest distance from each candidate to anything picked so far
+    let mut min_distances = vec![f32::INFINITY; candidates.len()];
+    let update = |min_distances: &mut [f32], picked: &[Oklab]| {
+        for (min_distance, candidate) in min_distances.iter_mut().zip(&candidate_views) {
+            *min_distance = min_distance.min(distance(candidate, picked));
+        }
+    };
+    for colour in avoid {
+        update(&mut min_distances, &views(*colour));
+    }
+
+    let mut palette = Vec::with_capacity(len);
+    for _ in 0..len {
+        // Ties go to the earliest candidate so the palette is always the same
+        let mut best = 0;
+        for (idx, min_distance) in min_distances.iter().enumerate() {
+            if *min_distance > min_distances[best] {
+                best = idx;
+            }
+        }
+
+        palette.push(to_srgb(candidates[best]));
+        update(&mut min_distances, &candidate_views[best]);
+    }
+
+    palette
+}
+
+/// Every in gamut colour on a grid over the allowed lightness and chroma, and the whole hue circle
+fn candidates(options: &PaletteOptions) -> Vec<Oklab> {
+    const STEPS: usize = 4;
+    const HUE_STEP: usize = 3;
+
+    let lerp =
+        |(from, to): (f32, f32), step: usize| from + (to - from) * step as f32 / (STEPS - 1) as f32;
+
+    let mut candidates = Vec::new();
+    for hue in (0..360).step_by(HUE_STEP) {
+        for l_step in 0..STEPS {
+            for c_step in 0..STEPS {
+                let oklab = Oklab::from(Oklch {
+                    l: lerp(options.lightness, l_step),
+                    c: lerp(options.chroma, c_step),
+                    h: tincture::Hue::from_degrees(hue as f32).unwrap(),
+                });
+
+                if in_gamut(oklab) {
+                    candidates.push(oklab);
+                }
+            }
+        }
+    }
+
+    candidates
+}
+
+pub(crate) fn in_gamut(oklab: Oklab) -> bool {
+    let lrgb: LinearRgb = tincture::convert(oklab);
+    [lrgb.r, lrgb.g, lrgb.b]
+        .iter()
+        .all(|n| (0.0..=1.0).contains(n))
+}
+
+/// Smallest ΔE between any view of one colour and the matching view of another
+fn distance(a: &[Oklab], b: &[Oklab]) -> f32 {
+    a.iter()
+        .zip(b)
+        .map(|(a, b)| ((a.l - b.l).powi(2) + (a.a - b.a).powi(2) + (a.b - b.b).powi(2)).sqrt())
+        .fold(f32::INFINITY, f32::min)
+}
+
+pub(crate) fn to_srgb(oklab: Oklab) -> Srgb {
+    let lrgb: LinearRgb = tincture::convert(oklab);
+    let clamp = |n: f32| n.clamp(0.0, 1.0);
+    Srgb::from(LinearRgb {
+        r: clamp(lrgb.r),
+        g: clamp(lrgb.g),
+        b: clamp(lrgb.b),
+    })
+}
+
+// Machado, Oliveira and Fernandes (2009) at full severity, applied to linear RGB
+const DEUTERANOPIA: [[f32; 3]; 3] = [
+    [0.367322, 0.860646, -0.227968],
+    [0.280085, 0.672501, 0.047413],
+    [-0.011820, 0.042940, 0.968881],
+];
+const PROTANOPIA: [[f32; 3]; 3] = [
+    [0.152286, 1.052583, -0.204868],
+    [0.114503, 0.786281, 0.099216],
+    [-0.003882, -0.048116, 1.051998],
+];
+
+fn simulate(colour: Oklab, matrix: &[[f32; 3]; 3]) -> Oklab {
+    let lrgb: LinearRgb = tincture::convert(colour);
+    let row = |[r, g, b]: [f32; 3]| (r * lrgb.r + g * lrgb.g + b * lrgb.b).clamp(0.0, 1.0);
+
+    tincture::convert(LinearRgb {
+        r: row(matrix[0]),
+        g: row(matrix[1]),
+        b: row(matrix[2]),
+    })
+}
+
+#[cfg(test)]
+#[test]
+fn test_all_colours() {
+    use tincture::ColorSpace;
+
+    for colour_blind in [false, true] {
+        let options = PaletteOptions {
+            lightness: (0.7, 0.85),
+            chroma: (0.08, 0.16),
+            colour_blind,
+        };
+        let palette = gen_palette(40, &options, &[]);
+
+        assert_eq!(palette.len(), 40);
+        assert!(palette.iter().all(|colour| colour.in_bounds()));
+        // Growing the palette keeps the colours already in it
+        assert_eq!(gen_palette(10, &options, &[]), palette[..10]);
+    }
+}
@@ -0,0 +1,215 @@
+use super::bell_times::{self, BellTime};
+use crate::types::Period;
+use serde::Deserialize;
+
+/// `[events.free_periods]` in the config file
+///
+/// ```toml
+/// [events.free_periods]
+/// # hide, free, school_day or study
+/// leading = "school_day"
+/// trailing = "school_day"
+/// mid_day = "study"
+///
+/// [events.free_periods.study]
+/// room = "Library"
+/// ```
+///
+/// Free periods before the first class (or break) of the day are leading,
+/// ones after the last are trailing, and a day without anything only has trailing ones.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct FreePeriods {
+    leading: FreePolicy,
+    trailing: FreePolicy,
+    mid_day: FreePolicy,
+    /// Where a regular school day starts, for `school_day`
+    first_period: Period,
+    /// Where a regular school day ends, for `school_day`
+    last_period: Period,
+    study: Study,
+}
+
+impl Default for FreePeriods {
+    fn default() -> Self {
+        Self {
+            leading: FreePolicy::SchoolDay,
+            trailing: FreePolicy::Hide,
+            mid_day: FreePolicy::Free,
+            first_period: Period::P1,
+            last_period: Period::P6,
+            study: Study::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum FreePolicy {
+    Hide,
+    /// Show as "Free Period"
+    Free,
+    /// Show as "Free Period" if it's during a regular school day, otherwise hide
+    SchoolDay,
+    /// Show as study
+    Study,
+}
+
+/// What free periods are shown as with the `study` policy
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Study {
+    pub(crate) title: String,
+    pub(crate) room: Option<String>,
+}
+
+impl Default for Study {
+    fn default() -> Self {
+        Self {
+            title: "Study".to_string(),
+            room: None,
+        }
+    }
+}
+
+/// How a free period ends up being shown
+pub(crate) enum FreeEvent<'a> {
+    Hidden,
+    Free,
+    Study(&'a Study),
+}
+
+impl FreePeriods {
+    /// Follows the policy for a free period at `idx` in the day's bell times,
+    /// given where the first and last lessons which aren't free are
+    pub(crate) fn free_event(
+        &self,
+        bell_times: &bell_times::Day,
+        idx: usize,
+        not_free: Option<(usize, usize)>,
+    ) -> FreeEvent<'_> {
+        let policy = match not_free {
+            Some((first, _)) if idx < first => self.leading,
+            Some((_, last)) if idx > last => self.trailing,
+            Some(_) => self.mid_day,
+            None => self.trailing,
+        };
+
+        match policy {
+            FreePolicy::Hide => FreeEvent::Hidden,
+            FreePolicy::Free => FreeEvent::Free,
+            FreePolicy::SchoolDay
+                if self.in_school_day(bell_times, &bell_times.bell_times[idx]) =>
+            {
+                FreeEvent::Free
+            }
+            FreePolicy::SchoolDay => FreeEvent::Hidden,
+            FreePolicy::Study => FreeEvent::Study(&self.study),
+        }
+    }
+
+    /// What free periods are shown as with the `study` policy
+    pub(crate) fn study(&self) -> &Study {
+        &self.study
+    }
+
+    /// Days without the first or last period aren't cut short at that end
+    fn in_school_day(&self, bell_times: &bell_times::Day, bell_time: &BellTime) -> bool {
+        let after_start = bell_times
+            .get(self.first_period)
+            .is_none_or(|first| bell_time.start() >= first.start());
+        let before_end = bell_times
+            .get(self.last_period)
+            .is_none_or(|last| bell_time.end() <= last.end());
+
+        after_start && before_end
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_free_periods() {
+    use super::bell_times::BellTimes;
+    use super::template::Templates;
+    use super::{timetable_events, EventOptions};
+    use crate::types::{Day, DayOfWeek, Lesson, Timetable, Week};
+
+    let lesson = Lesson::class("MAT");
+
+    // Free until P3, then free again from P5
+    let mut week = Week::default();
+    week.days.insert(
+        DayOfWeek::Monday,
+        Day::from_lessons([
+            (Period::P0, Lesson::FreePeriod),
+            (Period::P1, Lesson::FreePeriod),
+            (Period::P2, Lesson::FreePeriod),
+            (Period::Recess, Lesson::Recess),
+            (Period::P3, lesson.clone()),
+            (Period::P4, Lesson::FreePeriod),
+            (Period::Lunch1, Lesson::Lunch1),
+            (Period::Lunch2, Lesson::Lunch2),
+            (Period::P5, lesson),
+            (Period::P6, Lesson::FreePeriod),
+            (Period::P7, Lesson::AbsentPeriod),
+            (Period::P8, Lesson::FreePeriod),
+        ])
+        .unwrap(),
+    );
+    let t = Timetable { weeks: vec![week] };
+
+    let events = |free_periods: &str| {
+        let options: EventOptions =
+            toml::from_str(&format!("[free_periods]\n{free_periods}")).unwrap();
+        let templates = Templates::default();
+        timetable_events(&t, &BellTimes::default(), &options)
+            .unwrap()
+            .iter()
+            .map(|event| {
+                let title = templates.title(event);
+                match templates.info(event) {
+                    Some(info) => format!("{} {title} {info}", event.period),
+                    None => format!("{} {title}", event.period),
+                }
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // The same as before there were policies
+    assert_eq!(
+        events(""),
+        [
+            "P1 Free Period",
+            "P2 Free Period",
+            "Recess Recess",
+            "P3 MAT.1",
+            "P4 Free Period",
+            "Lunch1 Lunch",
+            "P5 MAT.1"
+        ]
+    );
+
+    assert_eq!(
+        events(
+            r#"
+            leading = "hide"
+            trailing = "school_day"
+            mid_day = "study"
+            study = { room = "Library" }
+            "#
+        ),
+        [
+            "Recess Recess",
+            "P3 MAT.1",
+            "P4 Study Library",
+            "Lunch1 Lunch",
+            "P5 MAT.1",
+            "P6 Free Period"
+        ]
+    );
+
+    assert_eq!(
+        events(r#"trailing = "free""#)[7..],
+        ["P6 Free Period", "P7 Free Period", "P8 Free Period"]
+    );
+}
@@ -0,0 +1,253 @@
+use self::css_colours::CSS_COLOURS;
+use super::bell_times::BellTimes;
+use super::colours::{from_hex, Palette};
+use super::template::Templates;
+use super::{timetable_events, Event, EventOptions};
+use crate::calendar::DatedEvent;
+use crate::types::{DayOfWeek, Lesson, Timetable};
+use chrono::{Duration, NaiveDate, NaiveDateTime, Utc};
+use tincture::{LinearRgb, Oklab, Srgb};
+
+mod css_colours;
+
+const TZID: &str = "Australia/Sydney";
+
+// NSW daylight saving: AEDT from the first Sunday in October, AEST from the first Sunday in April
+const VTIMEZONE: &str = "BEGIN:VTIMEZONE\r
+TZID:Australia/Sydney\r
+BEGIN:STANDARD\r
+DTSTART:19700405T030000\r
+TZOFFSETFROM:+1100\r
+TZOFFSETTO:+1000\r
+TZNAME:AEST\r
+RRULE:FREQ=YEARLY;BYMONTH=4;BYDAY=1SU\r
+END:STANDARD\r
+BEGIN:DAYLIGHT\r
+DTSTART:19701004T020000\r
+TZOFFSETFROM:+1000\r
+TZOFFSETTO:+1100\r
+TZNAME:AEDT\r
+RRULE:FREQ=YEARLY;BYMONTH=10;BYDAY=1SU\r
+END:DAYLIGHT\r
+END:VTIMEZONE\r
+";
+
+/// Generates an iCalendar feed where every event repeats once per cycle,
+/// with week A starting on the Monday `week_a_start` and every other week following on.
+pub(crate) fn gen_timetable_ics(
+    t: &Timetable,
+    bell_times: &BellTimes,
+    palette: &Palette,
+    templates: &Templates,
+    options: &EventOptions,
+    week_a_start: NaiveDate,
+    student_id: &str,
+) -> anyhow::Result<String> {
+    let mut feed = Feed::new(palette, templates, student_id);
+
+    let rrule = format!("FREQ=WEEKLY;INTERVAL={}", t.weeks.len());
+    for event in timetable_events(t, bell_times, options)? {
+        let day_offset = DayOfWeek::iter()
+            .position(|day_of_week| day_of_week == event.day_of_week)
+            .unwrap();
+        let week_offset = event.week * 7;
+        let date = week_a_start + Duration::days((week_offset + day_offset) as i64);
+
+        feed.write_event(&event, date, None, Some(&rrule))?;
+    }
+
+    Ok(feed.finish())
+}
+
+/// Generates an iCalendar feed with a separate event for every lesson,
+/// so holidays and pupil free days are left out
+pub(crate) fn gen_dated_ics(
+    events: &[DatedEvent<'_>],
+    palette: &Palette,
+    templates: &Templates,
+    student_id: &str,
+) -> anyhow::Result<String> {
+    let mut feed = Feed::new(palette, templates, student_id);
+
+    for dated_event in events {
+        feed.write_event(
+            &dated_event.event,
+            dated_event.date,
+            dated_event.replaced_by,
+            None,
+        )?;
+    }
+
+    Ok(feed.finish())
+}
+
+struct Feed<'a> {
+    ics: String,
+    palette: &'a Palette,
+    templates: &'a Templates,
+    student_id: &'a str,
+    dtstamp: String,
+}
+
+impl<'a> Feed<'a> {
+    fn new(palette: &'a Palette, templates: &'a Templates, student_id: &'a str) -> Self {
+        let mut ics = String::new();
+        ics += "BEGIN:VCALENDAR\r\n";
+        ics += "VERSION:2.0\r\n";
+        ics += "PRODID:-//lhvy//timetable//EN\r\n";
+        ics += "CALSCALE:GREGORIAN\r\n";
+        ics += VTIMEZONE;
+
+        Self {
+            ics,
+            palette,
+            templates,
+            student_id,
+            dtstamp: Utc::now().format("%Y%m%dT%H%M%SZ").to_string(),
+        }
+    }
+
+    fn finish(mut self) -> String {
+        self.ics += "END:VCALENDAR\r\n";
+        self.ics
+    }
+
+    fn write_event(
+        &mut self,
+        event: &Event<'_>,
+        date: NaiveDate,
+        replaced_by: Option<&str>,
+        rrule: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let ics = &mut self.ics;
+        let start = date.and_time(event.start);
+        let end = date.and_time(event.end);
+
+        *ics += "BEGIN:VEVENT\r\n";
+        write_property(
+            ics,
+            "UID",
+            &format!(
+                "{}-{}@timetable.lhvy.dev",
+                start.format("%Y%m%dT%H%M%S"),
+                self.student_id
+            ),
+        );
+        write_property(ics, "DTSTAMP", &self.dtstamp);
+        write_property(ics, &format!("DTSTART;TZID={TZID}"), &format_local(start));
+        write_property(ics, &format!("DTEND;TZID={TZID}"), &format_local(end));
+        if let Some(rrule) = rrule {
+            write_property(ics, "RRULE", rrule);
+        }
+
+        // A special day taking over the lesson has nothing but its name
+        if let Some(name) = replaced_by {
+            write_property(ics, "SUMMARY", &escape_text(name));
+            write_property(ics, "COLOR", css_colour_name(self.palette.neutral()));
+            *ics += "END:VEVENT\r\n";
+            return Ok(());
+        }
+
+        write_property(ics, "SUMMARY", &escape_text(&self.templates.title(event)));
+        write_property(
+            ics,
+            "COLOR",
+            css_colour_name(self.palette.event_colour(event)?),
+        );
+
+        if let Some(info) = self.templates.info(event) {
+            write_property(ics, "LOCATION", &escape_text(&info));
+        }
+
+        if let Lesson::Present {
+            subject, teacher, ..
+        } = event.lesson
+        {
+            let description = match teacher {
+                Some(teacher) => format!("{}\n{}", subject.name, teacher.name()),
+                None => subject.name.clone(),
+            };
+            write_property(ics, "DESCRIPTION", &escape_text(&description));
+        }
+
+        *ics += "END:VEVENT\r\n";
+
+        Ok(())
+    }
+}
+
+/// Closest CSS colour name by distance in Oklab
+fn css_colour_name(colour: Srgb) -> &'static str {
+    let to_oklab = |colour: Srgb| -> Oklab { tincture::convert(LinearRgb::from(colour)) };
+    let target = to_oklab(colour);
+
+    let distance = |hex: u32| {
+        let oklab = to_oklab(from_hex(hex));
+        (oklab.l - target.l).powi(2) + (oklab.a - target.a).powi(2) + (oklab.b - target.b).powi(2)
+    };
+
+    CSS_COLOURS
+        .iter()
+        .min_by(|(_, a), (_, b)| distance(*a).total_cmp(&distance(*b)))
+        .map(|(name, _)| *name)
+        .unwrap()
+}
+
+fn format_local(date_time: NaiveDateTime) -> String {
+    date_time.format("%Y%m%dT%H%M%S").to_string()
+}
+
+/// Escapes a TEXT value as per RFC 5545 section 3.3.11
+fn escape_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            ';' => escaped.push_str("\\;"),
+            ',' => escaped.push_str("\\,"),
+            '\n' => escaped.push_str("\\n"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Writes a content line, folding it so no line is longer than 75 octets
+fn write_property(ics: &mut String, name: &str, value: &str) {
+    let line = format!("{name}:{value}");
+
+    let mut line_len = 0;
+    for c in line.chars() {
+        if line_len + c.len_utf8() > 75 {
+            *ics += "\r\n ";
+            line_len = 1;
+        }
+        ics.push(c);
+        line_len += c.len_utf8();
+    }
+    *ics += "\r\n";
+}
+
+#[cfg(test)]
+#[test]
+fn test_fold_long_lines() {
+    let mut ics = String::new();
+    write_property(&mut ics, "DESCRIPTION", &"a".repeat(100));
+
+    for line in ics.split("\r\n") {
+        assert!(line.len() <= 75);
+    }
+    assert_eq!(
+        ics.replace("\r\n ", ""),
+        format!("DESCRIPTION:{}\r\n", "a".repeat(100))
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn test_css_colour_name() {
+    let colour = |r, g, b| Srgb { r, g, b };
+    assert_eq!(css_colour_name(colour(1.0, 0.0, 0.0)), "red");
+    assert_eq!(css_colour_name(colour(1.0, 1.0, 1.0)), "white");
+    assert_eq!(css_colour_name(from_hex(0x6495ed)), "cornflowerblue");
+}
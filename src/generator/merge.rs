@@ -0,0 +1,195 @@
+use super::Event;
+use crate::types::{Lesson, Period};
+use chrono::NaiveTime;
+use serde::Deserialize;
+
+/// Whether back to back lessons of the same class become one event
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum MergeDoubles {
+    #[default]
+    Never,
+    /// Join lessons in periods next to each other, stopping at recess and lunch
+    WithinBreaks,
+    /// Also join lessons which carry on through recess or lunch,
+    /// leaving the break out
+    AcrossBreaks,
+}
+
+impl MergeDoubles {
+    /// Whether `next` is the same class as `event` on the same day,
+    /// with the same teacher and room
+    fn joins(self, event: &Event<'_>, next: &Event<'_>) -> bool {
+        let is_break = |period| matches!(period, Period::Recess | Period::Lunch1 | Period::Lunch2);
+
+        match self {
+            MergeDoubles::Never => return false,
+            MergeDoubles::WithinBreaks if is_break(event.period) || is_break(next.period) => {
+                return false
+            }
+            MergeDoubles::WithinBreaks | MergeDoubles::AcrossBreaks => {}
+        }
+
+        if (event.week, event.day_of_week) != (next.week, next.day_of_week) {
+            return false;
+        }
+
+        match (event.lesson, next.lesson) {
+            (
+                Lesson::Present {
+                    teacher,
+                    room,
+                    subject_code,
+                    class_code,
+                    ..
+                },
+                Lesson::Present {
+                    teacher: next_teacher,
+                    room: next_room,
+                    subject_code: next_subject_code,
+                    class_code: next_class_code,
+                    ..
+                },
+            ) => {
+                subject_code == next_subject_code
+                    && class_code == next_class_code
+                    && teacher == next_teacher
+                    && room == next_room
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Joins runs of events which carry on from each other into one,
+/// running from the first one's start to the last one's end.
+///
+/// `event` gives the event of an item which can be joined, or `None` for one which can't.
+pub(crate) fn merge_doubles<'e, T>(
+    items: &mut Vec<T>,
+    merge: MergeDoubles,
+    event: impl Fn(&mut T) -> Option<&mut Event<'e>>,
+) {
+    enum Step {
+        Join(NaiveTime),
+        HoldBreak,
+        Keep,
+    }
+
+    let mut merged: Vec<T> = Vec::with_capacity(items.len());
+    // Breaks straight after the last merged item,
+    // held back until it's known whether its class carries on through them
+    let mut breaks: Vec<T> = Vec::new();
+
+    for mut item in items.drain(..) {
+        let step = match (merged.last_mut().and_then(&event), event(&mut item)) {
+            (Some(last), Some(next)) => {
+                let end = breaks
+                    .last_mut()
+                    .and_then(&event)
+                    .map_or(last.end, |held| held.end);
+                let is_break = matches!(
+                    next.lesson,
+                    Lesson::Recess | Lesson::Lunch1 | Lesson::Lunch2
+                );
+
+                if end != next.start {
+                    Step::Keep
+                } else if merge.joins(last, next) {
+                    Step::Join(next.end)
+                } else if merge == MergeDoubles::AcrossBreaks
+                    && is_break
+                    && matches!(last.lesson, Lesson::Present { .. })
+                    && (last.week, last.day_of_week) == (next.week, next.day_of_week)
+                {
+                    Step::HoldBreak
+                } else {
+                    Step::Keep
+                }
+            }
+            _ => Step::Keep,
+        };
+
+        match step {
+            Step::Join(end) => {
+                if let Some(last) = merged.last_mut().and_then(&event) {
+                    last.end = end;
+                }
+                breaks.clear();
+            }
+            Step::HoldBreak => breaks.push(item),
+            Step::Keep => {
+                merged.append(&mut breaks);
+                merged.push(item);
+            }
+        }
+    }
+    merged.append(&mut breaks);
+
+    *items = merged;
+}
+
+#[cfg(test)]
+#[test]
+fn test_merge_doubles() {
+    use super::bell_times::BellTimes;
+    use super::{timetable_events, EventOptions};
+    use crate::types::{Day, DayOfWeek, Timetable, Week};
+
+    let lesson = |subject_code: &str, room: &str| Lesson::class(subject_code).in_room(room);
+
+    let mut week = Week::default();
+    week.days.insert(
+        DayOfWeek::Monday,
+        Day::from_lessons([
+            (Period::P1, lesson("MAT", "M1")),
+            (Period::P2, lesson("MAT", "M1")),
+            // Carries on through recess
+            (Period::Recess, Lesson::Recess),
+            (Period::P3, lesson("MAT", "M1")),
+            // Same class in a different room
+            (Period::P4, lesson("MAT", "M2")),
+            (Period::Lunch1, Lesson::Lunch1),
+            (Period::Lunch2, Lesson::Lunch2),
+            (Period::P5, lesson("ENG", "E1")),
+            (Period::P6, lesson("ENG", "E1")),
+        ])
+        .unwrap(),
+    );
+    let t = Timetable { weeks: vec![week] };
+
+    let times = |merge_doubles| {
+        let options = EventOptions {
+            merge_doubles,
+            ..EventOptions::default()
+        };
+        timetable_events(&t, &BellTimes::default(), &options)
+            .unwrap()
+            .iter()
+            .map(|event| {
+                format!(
+                    "{}-{}",
+                    event.start.format("%H:%M"),
+                    event.end.format("%H:%M")
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(times(MergeDoubles::Never).len(), 8);
+    assert_eq!(
+        times(MergeDoubles::WithinBreaks),
+        [
+            "08:55-10:45",
+            "10:45-11:05",
+            "11:05-12:00",
+            "12:00-12:55",
+            "12:55-13:35",
+            "13:35-15:25"
+        ]
+    );
+    assert_eq!(
+        times(MergeDoubles::AcrossBreaks),
+        ["08:55-12:00", "12:00-12:55", "12:55-13:35", "13:35-15:25"]
+    );
+}
@@ -0,0 +1,589 @@
+use super::bell_times::BellTimes;
+use super::free_periods::{FreePeriods, Study};
+use super::template::Templates;
+use crate::plist::{Dict, Value};
+use crate::scraper::LessonRules;
+use crate::types::{
+    week_name, Day, DayOfWeek, Lesson, LessonKind, Period, Subject, Timetable, Week, MAX_WEEKS,
+};
+use chrono::NaiveTime;
+use std::collections::BTreeMap;
+use tincture::Srgb;
+
+/// What can be recovered from a `.timetable` file
+pub(crate) struct TimetablePlist {
+    /// Subjects only have what the title template shows, so teachers are missing,
+    /// names are the subject code unless the template has them,
+    /// and subject codes are empty when it only has names
+    pub(crate) timetable: Timetable,
+    /// Colour of every event title
+    pub(crate) colours: BTreeMap<String, Srgb>,
+}
+
+/// Rebuilds a timetable from a Class Timetable plist, working out periods from the bell times,
+/// special lessons from the titles their rules give them and classes from the templates.
+/// Fails on a title the templates can't be undone for, rather than guessing.
+///
+/// Periods without an event are absent, apart from the second half of lunch
+/// when lunch in the first half covers it, and breaks a merged double runs across.
+pub(crate) fn parse_timetable_plist(
+    value: &Value,
+    bell_times: &BellTimes,
+    rules: &LessonRules,
+    templates: &Templates,
+    free_periods: &FreePeriods,
+) -> anyhow::Result<TimetablePlist> {
+    let root = value
+        .as_dict()
+        .ok_or_else(|| anyhow::anyhow!("expected a dictionary"))?;
+    let empty = Dict::new();
+    let settings = match root.get("Settings") {
+        Some(settings) => settings
+            .as_dict()
+            .ok_or_else(|| anyhow::anyhow!("Settings should be a dictionary"))?,
+        None => &empty,
+    };
+    let events = match root.get("WeekEvents") {
+        Some(events) => events
+            .as_array()
+            .ok_or_else(|| anyhow::anyhow!("WeekEvents should be an array"))?,
+        None => &[],
+    };
+
+    let events = events
+        .iter()
+        .enumerate()
+        .map(|(idx, event)| {
+            parse_event(event, bell_times, rules, templates, free_periods.study())
+                .map_err(|e| anyhow::anyhow!("invalid event {}: {}", idx, e))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let mut lessons: BTreeMap<(usize, DayOfWeek), BTreeMap<Period, Lesson>> = BTreeMap::new();
+    for event in &events {
+        let (week, day_of_week, period) = (event.week, event.day_of_week, event.period);
+        if lessons
+            .entry((week, day_of_week))
+            .or_default()
+            .insert(period, event.lesson.clone())
+            .is_some()
+        {
+            anyhow::bail!(
+                "week {} {:?} has more than one event in {}",
+                week_name(week),
+                day_of_week,
+                period
+            );
+        }
+    }
+
+    // Doubles merged into one event fill every period they run through most of,
+    // apart from breaks they were merged across
+    for event in &events {
+        let (Some(end), Lesson::Present { .. }) = (event.end, &event.lesson) else {
+            continue;
+        };
+        let day_lessons = lessons.entry((event.week, event.day_of_week)).or_default();
+        for bell_time in &bell_times.day(event.day_of_week).bell_times {
+            let middle = bell_time.start() + (bell_time.end() - bell_time.start()) / 2;
+            if event.start < bell_time.start() && middle < end {
+                day_lessons
+                    .entry(bell_time.period())
+                    .or_insert_with(|| match bell_time.period() {
+                        Period::Recess => Lesson::Recess,
+                        Period::Lunch1 => Lesson::Lunch1,
+                        Period::Lunch2 => Lesson::Lunch2,
+                        _ => event.lesson.clone(),
+                    });
+            }
+        }
+    }
+
+    let number_of_weeks = match settings.get("NumberOfWeeks") {
+        Some(number_of_weeks) => number_of_weeks
+            .as_i64()
+            .and_then(|number_of_weeks| usize::try_from(number_of_weeks).ok())
+            .filter(|number_of_weeks| *number_of_weeks <= MAX_WEEKS)
+            .ok_or_else(|| {
+                anyhow::anyhow!("NumberOfWeeks should be a positive integer up to {MAX_WEEKS}")
+            })?,
+        None => lessons.keys().map(|(week, _)| week + 1).max().unwrap_or(0),
+    };
+    if let Some((week, _)) = lessons.keys().find(|(week, _)| *week >= number_of_weeks) {
+        anyhow::bail!(
+            "there's an event in week {} but only {} weeks",
+            week_name(*week),
+            number_of_weeks
+        );
+    }
+
+    let mut weeks = Vec::new();
+    for week in 0..number_of_weeks {
+        let mut days = Week::default();
+        for day_of_week in DayOfWeek::iter().take(5) {
+            let mut day_lessons = lessons.remove(&(week, day_of_week)).unwrap_or_default();
+            for bell_time in &bell_times.day(day_of_week).bell_times {
+                let whole_lunch = day_lessons.get(&Period::Lunch1) == Some(&Lesson::Lunch1);
+                day_lessons
+                    .entry(bell_time.period())
+                    .or_insert_with(|| match bell_time.period() {
+                        Period::Lunch2 if whole_lunch => Lesson::Lunch2,
+                        _ => Lesson::AbsentPeriod,
+                    });
+            }
+
+            let day = Day::from_lessons(day_lessons).expect("periods are map keys");
+            days.days.insert(day_of_week, day);
+        }
+        weeks.push(days);
+    }
+
+    let mut colours = BTreeMap::new();
+    if let Some(colour_settings) = settings.get("ColorSettings") {
+        let colour_settings = colour_settings
+            .as_dict()
+            .ok_or_else(|| anyhow::anyhow!("ColorSettings should be a dictionary"))?;
+        for (title, colour) in colour_settings {
+            let colour = parse_colour(colour)
+                .ok_or_else(|| anyhow::anyhow!("colour for {} should be 3 reals", title))?;
+            colours.insert(title.clone(), colour);
+        }
+    }
+
+    Ok(TimetablePlist {
+        timetable: Timetable { weeks },
+        colours,
+    })
+}
+
+struct PlistEvent {
+    week: usize,
+    day_of_week: DayOfWeek,
+    /// Period the event starts in
+    period: Period,
+    start: NaiveTime,
+    end: Option<NaiveTime>,
+    lesson: Lesson,
+}
+
+fn parse_event(
+    event: &Value,
+    bell_times: &BellTimes,
+    rules: &LessonRules,
+    templates: &Templates,
+    study: &Study,
+) -> anyhow::Result<PlistEvent> {
+    let time = |key| {
+        event
+            .as_dict()
+            .and_then(|event| event.get(key))
+            .map(|time| {
+                time.as_f64()
+                    .and_then(|seconds| {
+                        NaiveTime::from_num_seconds_from_midnight_opt(seconds as u32, 0)
+                    })
+                    .ok_or_else(|| anyhow::anyhow!("{} should be seconds since midnight", key))
+            })
+            .transpose()
+    };
+
+    let event = event
+        .as_dict()
+        .ok_or_else(|| anyhow::anyhow!("expected a dictionary"))?;
+    let get = |key: &str| {
+        event
+            .get(key)
+            .ok_or_else(|| anyhow::anyhow!("missing {}", key))
+    };
+
+    let week = get("weekNum")?
+        .as_i64()
+        .and_then(|week| usize::try_from(week).ok())
+        .filter(|week| *week < MAX_WEEKS)
+        .ok_or_else(|| anyhow::anyhow!("weekNum should be a positive integer below {MAX_WEEKS}"))?;
+    let day_of_week = match get("dayNum")?.as_i64() {
+        Some(0) => DayOfWeek::Monday,
+        Some(1) => DayOfWeek::Tuesday,
+        Some(2) => DayOfWeek::Wednesday,
+        Some(3) => DayOfWeek::Thursday,
+        Some(4) => DayOfWeek::Friday,
+        _ => anyhow::bail!("dayNum should be between 0 and 4"),
+    };
+    let start = time("time")?.ok_or_else(|| anyhow::anyhow!("missing time"))?;
+    let end = time("endTime")?;
+    let title = get("title")?
+        .as_str()
+        .ok_or_else(|| anyhow::anyhow!("title should be a string"))?;
+    let info = match event.get("info") {
+        Some(info) => Some(
+            info.as_str()
+                .ok_or_else(|| anyhow::anyhow!("info should be a string"))?,
+        ),
+        None => None,
+    };
+
+    // The period the event starts in, which copes with times nudged in the app
+    let period = bell_times
+        .day(day_of_week)
+        .bell_times
+        .iter()
+        .find(|bell_time| bell_time.start() <= start && start < bell_time.end())
+        .map(|bell_time| bell_time.period())
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "{} on {:?} isn't in any period",
+                start.format("%H:%M"),
+                day_of_week
+            )
+        })?;
+
+    Ok(PlistEvent {
+        week,
+        day_of_week,
+        period,
+        start,
+        end,
+        lesson: match (period, parse_title(title, info, rules, templates, study)?) {
+            // Lunch on its own in the second half, after a class in the first
+            (Period::Lunch2, Lesson::Lunch1) => Lesson::Lunch2,
+            (_, lesson) => lesson,
+        },
+    })
+}
+
+/// Undoes the titles special lesson rules give, or else the title and info templates
+fn parse_title(
+    title: &str,
+    info: Option<&str>,
+    rules: &LessonRules,
+    templates: &Templates,
+    study: &Study,
+) -> anyhow::Result<Lesson> {
+    match title {
+        "Free Period" => return Ok(Lesson::FreePeriod),
+        "Recess" => return Ok(Lesson::Recess),
+        "Lunch" => return Ok(Lesson::Lunch1),
+        _ if title == study.title => return Ok(Lesson::FreePeriod),
+        _ => {}
+    }
+
+    let room = match info {
+        Some(info) => templates.unrender_info(info)?.room,
+        None => None,
+    };
+    if let Some(lesson) = rules.unrender_title(title, room.as_deref())? {
+        return Ok(lesson);
+    }
+
+    let values = templates.unrender_title(title)?;
+    let name = values
+        .subject
+        .or_else(|| values.subject_code.clone())
+        .ok_or_else(|| anyhow::anyhow!("{:?} has no subject or subject code", title))?;
+
+    Ok(Lesson::Present {
+        subject: Subject {
+            name,
+            faculty: values.faculty.unwrap_or_default(),
+            kind: LessonKind::Regular,
+        },
+        teacher: None,
+        room: values.room.or(room),
+        subject_code: values.subject_code.unwrap_or_default(),
+        class_code: values.class_code.unwrap_or_default(),
+    })
+}
+
+fn parse_colour(value: &Value) -> Option<Srgb> {
+    match value.as_array()? {
+        [r, g, b] => Some(Srgb {
+            r: r.as_f64()? as f32,
+            g: g.as_f64()? as f32,
+            b: b.as_f64()? as f32,
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::colours::{event_colours, hex, ColourConfig, ColourRegistry};
+    use crate::generator::merge::MergeDoubles;
+    use crate::generator::template::Templates;
+    use crate::generator::{gen_timetable_plist, timetable_events, EventOptions, SelectedWeek};
+    use crate::plist::{from_xml, to_xml};
+    use chrono::Utc;
+
+    /// A timetable with everything the generator writes, and nothing it loses
+    fn timetable(bell_times: &BellTimes) -> Timetable {
+        let subjects = ["MAT", "ENG", "COM", "SCI", "MUS", "ART", "PDH", "HIS"];
+
+        let weeks = (0..2)
+            .map(|week| {
+                let mut days = Week::default();
+                for (day_idx, day_of_week) in DayOfWeek::iter().take(5).enumerate() {
+                    let bell_times = &bell_times.day(day_of_week).bell_times;
+                    let last = bell_times.len() - 1 - (week + day_idx) % 3;
+
+                    let lessons = bell_times.iter().enumerate().map(|(idx, bell_time)| {
+                        let period = bell_time.period();
+                        let lesson = match period {
+                            _ if idx == 0 || idx > last => Lesson::AbsentPeriod,
+                            Period::Recess => Lesson::Recess,
+                            Period::Lunch1 if week == 0 => Lesson::Lunch1,
+                            Period::Lunch2 => Lesson::Lunch2,
+                            Period::Assembly => Lesson::assembly().in_room("Hall"),
+                            // Free periods at the end of the day aren't exported
+                            _ if (idx + day_idx) % 5 == 0 && idx < last => Lesson::FreePeriod,
+                            _ => {
+                                // Mostly doubles, with Monday's P2 class carrying on after recess
+                                let pair = match period {
+                                    Period::P3 if day_idx == 0 => 1,
+                                    _ => idx / 2,
+                                };
+                                let subject_code =
+                                    subjects[(pair + day_idx + week) % subjects.len()];
+                                let lesson = Lesson::class(subject_code);
+                                match pair % 4 {
+                                    0 => lesson,
+                                    _ => lesson.in_room(&format!("R{pair}")),
+                                }
+                            }
+                        };
+                        (period, lesson)
+                    });
+
+                    days.days
+                        .insert(day_of_week, Day::from_lessons(lessons).unwrap());
+                }
+                days
+            })
+            .collect();
+
+        Timetable { weeks }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let bell_times = BellTimes::default();
+        let t = timetable(&bell_times);
+
+        let mut registry = ColourRegistry::default();
+        registry.register(&t);
+        let palette = registry
+            .palette(&ColourConfig::default(), &LessonRules::default())
+            .unwrap();
+        let selected_week = SelectedWeek {
+            week: 1,
+            updated: Utc::now(),
+        };
+
+        let templates = Templates::default();
+
+        for merge_doubles in [MergeDoubles::Never, MergeDoubles::AcrossBreaks] {
+            let options = EventOptions {
+                merge_doubles,
+                ..EventOptions::default()
+            };
+            let plist = gen_timetable_plist(
+                &t,
+                &bell_times,
+                &selected_week,
+                &palette,
+                &templates,
+                &options,
+            )
+            .unwrap();
+            let xml = to_xml(&plist);
+            let parsed = parse_timetable_plist(
+                &from_xml(&xml).unwrap(),
+                &bell_times,
+                &LessonRules::default(),
+                &templates,
+                &options.free_periods,
+            )
+            .unwrap();
+            assert_eq!(parsed.timetable, t);
+
+            let colours = event_colours(
+                &timetable_events(&t, &bell_times, &options).unwrap(),
+                &palette,
+                &templates,
+            )
+            .unwrap();
+            let hexes = |colours: &BTreeMap<String, Srgb>| {
+                colours
+                    .iter()
+                    .map(|(title, colour)| (title.clone(), hex(*colour)))
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(hexes(&parsed.colours), hexes(&colours));
+
+            // Writing it out again gives the same file
+            let again = gen_timetable_plist(
+                &parsed.timetable,
+                &bell_times,
+                &selected_week,
+                &palette,
+                &templates,
+                &options,
+            );
+            assert_eq!(again.unwrap(), plist);
+        }
+    }
+
+    #[test]
+    fn test_configured_templates() {
+        let bell_times = BellTimes::default();
+        let mut week = Week::default();
+        week.days.insert(
+            DayOfWeek::Monday,
+            Day::from_lessons([(
+                Period::P1,
+                Lesson::class("COM")
+                    .named("Commerce")
+                    .in_room("H1")
+                    .taught_by("J", "Smith"),
+            )])
+            .unwrap(),
+        );
+        let t = Timetable { weeks: vec![week] };
+
+        let mut registry = ColourRegistry::default();
+        registry.register(&t);
+        let palette = registry
+            .palette(&ColourConfig::default(), &LessonRules::default())
+            .unwrap();
+        let selected_week = SelectedWeek {
+            week: 0,
+            updated: Utc::now(),
+        };
+        let options = EventOptions::default();
+        let templates: Templates = toml::from_str(
+            r#"
+            title = "{subject} ({class_code})"
+            info = "{room} with {teacher}"
+            "#,
+        )
+        .unwrap();
+        let parse = |plist: &Value, templates: &Templates| {
+            parse_timetable_plist(
+                plist,
+                &bell_times,
+                &LessonRules::default(),
+                templates,
+                &options.free_periods,
+            )
+        };
+
+        let plist = gen_timetable_plist(
+            &t,
+            &bell_times,
+            &selected_week,
+            &palette,
+            &templates,
+            &options,
+        )
+        .unwrap();
+        let parsed = parse(&plist, &templates).unwrap();
+        // Import finds the subject code from the name
+        assert_eq!(
+            parsed.timetable.weeks[0].days[&DayOfWeek::Monday].get(Period::P1),
+            Some(&Lesson::Present {
+                subject: Subject {
+                    name: "Commerce".to_string(),
+                    faculty: String::new(),
+                    kind: LessonKind::Regular,
+                },
+                teacher: None,
+                room: Some("H1".to_string()),
+                subject_code: String::new(),
+                class_code: "1".to_string(),
+            })
+        );
+
+        // Titles the template can't be undone for aren't guessed at
+        let templates: Templates = toml::from_str(r#"title = "Class {subject_code}""#).unwrap();
+        let error = parse(&plist, &templates).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "invalid event 0: \"Commerce (1)\" doesn't fit the title template \"Class {subject_code}\""
+        );
+    }
+
+    #[test]
+    fn test_edited_in_app() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+	<key>WeekEvents</key>
+	<array>
+		<dict>
+			<key>dayNum</key>
+			<integer>0</integer>
+			<key>time</key>
+			<integer>32400</integer>
+			<key>endTime</key>
+			<integer>35700</integer>
+			<key>title</key>
+			<string>MAT.2</string>
+			<key>info</key>
+			<string>M1</string>
+			<key>weekNum</key>
+			<integer>0</integer>
+		</dict>
+		<dict>
+			<key>dayNum</key>
+			<integer>4</integer>
+			<key>time</key>
+			<real>39900</real>
+			<key>title</key>
+			<string>Band</string>
+			<key>weekNum</key>
+			<integer>1</integer>
+		</dict>
+	</array>
+</dict>
+</plist>"#;
+
+        let bell_times = BellTimes::default();
+        let parsed = parse_timetable_plist(
+            &from_xml(xml).unwrap(),
+            &bell_times,
+            &LessonRules::default(),
+            &Templates::default(),
+            &FreePeriods::default(),
+        )
+        .unwrap();
+        let weeks = &parsed.timetable.weeks;
+        assert_eq!(weeks.len(), 2);
+
+        // 9:00 is part way through P1
+        let monday = &weeks[0].days[&DayOfWeek::Monday];
+        assert_eq!(
+            monday.get(Period::P1),
+            Some(&Lesson::Present {
+                subject: Subject {
+                    name: "MAT".to_string(),
+                    faculty: String::new(),
+                    kind: LessonKind::Regular,
+                },
+                teacher: None,
+                room: Some("M1".to_string()),
+                subject_code: "MAT".to_string(),
+                class_code: "2".to_string(),
+            })
+        );
+        assert_eq!(monday.get(Period::P2), Some(&Lesson::AbsentPeriod));
+        // Without lunch in the first half there's nothing covering the second
+        assert_eq!(monday.get(Period::Lunch2), Some(&Lesson::AbsentPeriod));
+
+        let friday = &weeks[1].days[&DayOfWeek::Friday];
+        assert!(matches!(
+            friday.get(Period::P3),
+            Some(Lesson::Present { subject_code, class_code, .. })
+                if subject_code == "Band" && class_code.is_empty()
+        ));
+    }
+}
@@ -0,0 +1,476 @@
+use super::Event;
+use crate::scraper::LessonRules;
+use crate::types::{Lesson, Period, Teacher};
+use serde::Deserialize;
+
+/// `[templates]` in the config file, used by every exporter
+///
+/// ```toml
+/// [templates]
+/// title = "{subject} ({class_code})"
+/// info = "{room} with {teacher}"
+/// ```
+///
+/// Placeholders are `{subject}`, `{faculty}`, `{subject_code}`, `{class_code}`, `{teacher}`,
+/// `{room}` and `{period}`, with `{{` and `}}` for braces.
+/// They're only used for classes, since breaks have fixed titles,
+/// and special lessons can have a title of their own.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Templates {
+    #[serde(default = "default_title")]
+    title: Template,
+    /// Shown under the title, i.e. as the location in iCalendar
+    #[serde(default = "default_info")]
+    info: Template,
+    /// `[[special_lessons]]` from the config file, for the titles they give
+    #[serde(skip)]
+    special_lessons: LessonRules,
+}
+
+impl Default for Templates {
+    fn default() -> Self {
+        Self {
+            title: default_title(),
+            info: default_info(),
+            special_lessons: LessonRules::default(),
+        }
+    }
+}
+
+fn default_title() -> Template {
+    "{subject_code}.{class_code}".parse().unwrap()
+}
+
+fn default_info() -> Template {
+    "{room}".parse().unwrap()
+}
+
+impl Templates {
+    pub(crate) fn set_special_lessons(&mut self, special_lessons: &LessonRules) {
+        self.special_lessons = special_lessons.clone();
+    }
+
+    pub(crate) fn title(&self, event: &Event<'_>) -> String {
+        if let Some(study) = event.study {
+            return study.title.clone();
+        }
+
+        match event.lesson {
+            Lesson::Present {
+                subject,
+                subject_code,
+                ..
+            } => match self.special_lessons.treatment(subject, subject_code).title {
+                Some(title) => title.render(event),
+                None => self.title.render(event),
+            },
+            Lesson::FreePeriod | Lesson::AbsentPeriod => "Free Period".to_string(),
+            Lesson::Recess => "Recess".to_string(),
+            Lesson::Lunch1 | Lesson::Lunch2 => "Lunch".to_string(),
+        }
+    }
+
+    /// Reads a class's values back out of its title, failing if it doesn't fit the template
+    pub(crate) fn unrender_title(&self, title: &str) -> anyhow::Result<Unrendered> {
+        self.title.unrender(title).ok_or_else(|| {
+            anyhow::anyhow!(
+                "{:?} doesn't fit the title template {:?}",
+                title,
+                self.title.to_string()
+            )
+        })
+    }
+
+    /// Like [`Templates::unrender_title`], for the info under it
+    pub(crate) fn unrender_info(&self, info: &str) -> anyhow::Result<Unrendered> {
+        self.info.unrender(info).ok_or_else(|| {
+            anyhow::anyhow!(
+                "{:?} doesn't fit the info template {:?}",
+                info,
+                self.info.to_string()
+            )
+        })
+    }
+
+    /// `None` when there's nothing to show, i.e. a class without a room
+    pub(crate) fn info(&self, event: &Event<'_>) -> Option<String> {
+        if let Some(study) = event.study {
+            return study.room.clone();
+        }
+
+        match event.lesson {
+            Lesson::Present { .. } => Some(self.info.render(event)).filter(|info| !info.is_empty()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub(crate) struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Subject,
+    Faculty,
+    SubjectCode,
+    ClassCode,
+    Teacher,
+    Room,
+    Period,
+}
+
+impl Template {
+    /// Fills in the placeholders from a class, leaving out anything it doesn't have.
+    ///
+    /// A missing value takes any text touching it with it, like the brackets in `({room})`,
+    /// along with the text separating it from the value before it
+    /// (or after it, when it comes first). So `{room} with {teacher}` is just the room
+    /// for a class without a teacher.
+    fn render(&self, event: &Event<'_>) -> String {
+        let Lesson::Present {
+            subject,
+            teacher,
+            room,
+            subject_code,
+            class_code,
+        } = event.lesson
+        else {
+            return String::new();
+        };
+
+        // Placeholders are `Some`, and empty when their value is missing
+        let values: Vec<Option<String>> = self
+            .parts
+            .iter()
+            .map(|part| match part {
+                Part::Text(_) => None,
+                Part::Subject => Some(subject.name.clone()),
+                Part::Faculty => Some(subject.faculty.clone()),
+                Part::SubjectCode => Some(subject_code.clone()),
+                Part::ClassCode => Some(class_code.clone()),
+                Part::Teacher => Some(teacher.as_ref().map(Teacher::name).unwrap_or_default()),
+                Part::Room => Some(room.clone().unwrap_or_default()),
+                Part::Period => Some(period_number(event.period)),
+            })
+            .collect();
+        let texts = self.texts(|idx| values[idx].as_deref() == Some(""));
+
+        let mut text = String::new();
+        for (value, part_text) in values.iter().zip(texts) {
+            text += value.as_deref().unwrap_or(part_text);
+        }
+
+        text.trim().to_string()
+    }
+}
+
+impl Template {
+    /// Text left in each part when the placeholders `missing` says have no value are taken out,
+    /// which is nothing for the placeholders themselves
+    fn texts(&self, missing: impl Fn(usize) -> bool) -> Vec<&str> {
+        let placeholder = |idx: usize| !matches!(self.parts.get(idx), Some(Part::Text(_)) | None);
+        let present = |idx: usize| placeholder(idx) && !missing(idx);
+        let mut texts: Vec<&str> = self
+            .parts
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.as_str(),
+                _ => "",
+            })
+            .collect();
+
+        for idx in (0..self.parts.len()).filter(|idx| placeholder(*idx) && missing(*idx)) {
+            let before = idx.checked_sub(1).filter(|before| !placeholder(*before));
+            let after =
+                Some(idx + 1).filter(|after| *after < self.parts.len() && !placeholder(*after));
+
+            if let Some(before) = before {
+                texts[before] = texts[before].trim_end_matches(|c: char| !c.is_whitespace());
+            }
+            if let Some(after) = after {
+                texts[after] = texts[after].trim_start_matches(|c: char| !c.is_whitespace());
+            }
+
+            let value_before = (0..idx).any(present);
+            let value_after = (idx + 1..self.parts.len()).any(present);
+            if let Some(before) = before.filter(|_| value_before || !value_after) {
+                texts[before] = "";
+            }
+            if let Some(after) = after.filter(|_| !value_before) {
+                texts[after] = "";
+            }
+        }
+
+        texts
+    }
+}
+
+/// Values read back out of a rendered template
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Unrendered {
+    pub(crate) subject: Option<String>,
+    pub(crate) faculty: Option<String>,
+    pub(crate) subject_code: Option<String>,
+    pub(crate) class_code: Option<String>,
+    pub(crate) room: Option<String>,
+}
+
+impl Template {
+    /// Reads values back out of text rendered from the template, failing if it doesn't fit.
+    /// Values left out when rendering are `None`, and when it isn't clear which ones were,
+    /// the later ones are.
+    /// Each value runs until the text after it, so two values next to each other
+    /// can't be told apart.
+    pub(crate) fn unrender(&self, text: &str) -> Option<Unrendered> {
+        let placeholders: Vec<usize> = (0..self.parts.len())
+            .filter(|idx| !matches!(self.parts[*idx], Part::Text(_)))
+            .collect();
+
+        // Bits of each mask are the placeholders left out, trying as few as possible first
+        let mut masks: Vec<u32> = (0..1 << placeholders.len()).collect();
+        masks.sort_by_key(|mask| (mask.count_ones(), std::cmp::Reverse(*mask)));
+        masks.into_iter().find_map(|mask| {
+            self.unrender_without(text, |idx| {
+                placeholders
+                    .iter()
+                    .position(|placeholder| *placeholder == idx)
+                    .is_some_and(|bit| mask & 1 << bit != 0)
+            })
+        })
+    }
+
+    fn unrender_without(&self, text: &str, missing: impl Fn(usize) -> bool) -> Option<Unrendered> {
+        // The template as it renders with those values left out
+        let texts = self.texts(&missing);
+        let mut parts: Vec<Part> = Vec::new();
+        for (idx, part) in self.parts.iter().enumerate() {
+            match part {
+                Part::Text(_) if texts[idx].is_empty() => {}
+                Part::Text(_) => match parts.last_mut() {
+                    Some(Part::Text(previous)) => *previous += texts[idx],
+                    _ => parts.push(Part::Text(texts[idx].to_string())),
+                },
+                _ if missing(idx) => {}
+                part => parts.push(part.clone()),
+            }
+        }
+
+        let mut values = Unrendered::default();
+        let mut rest = text;
+        for (idx, part) in parts.iter().enumerate() {
+            let value = match part {
+                Part::Text(literal) => {
+                    rest = rest.strip_prefix(literal.as_str())?;
+                    continue;
+                }
+                _ => match parts.get(idx + 1) {
+                    Some(Part::Text(next)) => {
+                        let (value, after) = rest.split_at(rest.find(next.as_str())?);
+                        rest = after;
+                        value
+                    }
+                    Some(_) => return None,
+                    None => std::mem::take(&mut rest),
+                },
+            };
+            // A value left in can't have been empty
+            if value.is_empty() {
+                return None;
+            }
+
+            let value = Some(value.to_string());
+            match part {
+                Part::Subject => values.subject = value,
+                Part::Faculty => values.faculty = value,
+                Part::SubjectCode => values.subject_code = value,
+                Part::ClassCode => values.class_code = value,
+                Part::Room => values.room = value,
+                Part::Text(_) | Part::Teacher | Part::Period => {}
+            }
+        }
+
+        rest.is_empty().then_some(values)
+    }
+}
+
+/// `1` for P1, and the name of anything else
+fn period_number(period: Period) -> String {
+    let name = period.to_string();
+    match name.strip_prefix('P') {
+        Some(number) if number.chars().all(|c| c.is_ascii_digit()) => number.to_string(),
+        _ => name,
+    }
+}
+
+impl std::str::FromStr for Template {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = text.chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = rest
+                        .find('}')
+                        .ok_or_else(|| format!("unclosed placeholder in {text:?}"))?;
+                    let part = match &rest[..end] {
+                        "subject" => Part::Subject,
+                        "faculty" => Part::Faculty,
+                        "subject_code" => Part::SubjectCode,
+                        "class_code" => Part::ClassCode,
+                        "teacher" => Part::Teacher,
+                        "room" => Part::Room,
+                        "period" => Part::Period,
+                        name => return Err(format!("unknown placeholder {{{name}}}")),
+                    };
+                    chars = rest[end + 1..].chars();
+
+                    if !literal.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut literal)));
+                    }
+                    parts.push(part);
+                }
+                '}' => return Err(format!("unmatched }} in {text:?}, use }}}} for a brace")),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Text(literal));
+        }
+
+        Ok(Self { parts })
+    }
+}
+
+impl std::fmt::Display for Template {
+    /// Writes the template as it would be given in the config file
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => write!(f, "{}", text.replace('{', "{{").replace('}', "}}"))?,
+                Part::Subject => write!(f, "{{subject}}")?,
+                Part::Faculty => write!(f, "{{faculty}}")?,
+                Part::SubjectCode => write!(f, "{{subject_code}}")?,
+                Part::ClassCode => write!(f, "{{class_code}}")?,
+                Part::Teacher => write!(f, "{{teacher}}")?,
+                Part::Room => write!(f, "{{room}}")?,
+                Part::Period => write!(f, "{{period}}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<String> for Template {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        text.parse()
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_templates() {
+    use crate::types::DayOfWeek;
+    use chrono::NaiveTime;
+
+    let lesson = Lesson::class("COM")
+        .named("Commerce")
+        .in_faculty("HSIE")
+        .taught_by("J", "Smith");
+    let event = Event {
+        day_of_week: DayOfWeek::Monday,
+        week: 0,
+        period: Period::P3,
+        start: NaiveTime::from_hms_opt(11, 5, 0).unwrap(),
+        end: NaiveTime::from_hms_opt(12, 0, 0).unwrap(),
+        lesson: &lesson,
+        study: None,
+    };
+
+    let templates = Templates::default();
+    assert_eq!(templates.title(&event), "COM.1");
+    assert_eq!(templates.info(&event), None);
+
+    let templates: Templates = toml::from_str(
+        r#"
+        title = "{subject} ({faculty}) {{{period}}}"
+        info = "{room} {teacher}"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(templates.title(&event), "Commerce (HSIE) {3}");
+    assert_eq!(templates.info(&event), Some("J Smith".to_string()));
+
+    // Missing values take their separators and brackets with them
+    let render = |template: &str, lesson: &Lesson| {
+        let event = Event { lesson, ..event };
+        template.parse::<Template>().unwrap().render(&event)
+    };
+    let in_room = Lesson::class("COM").in_room("H1");
+    assert_eq!(render("{room} with {teacher}", &in_room), "H1");
+    assert_eq!(render("{room} with {teacher}", &lesson), "J Smith");
+    assert_eq!(
+        render("{subject_code} {room} {teacher}", &lesson),
+        "COM J Smith"
+    );
+    assert_eq!(render("{subject_code} ({room})", &lesson), "COM");
+    assert_eq!(render("Room {room}", &lesson), "");
+
+    // Special lesson titles are read back when importing
+    let assembly: Template = "Assembly {subject_code}.{class_code}".parse().unwrap();
+    assert_eq!(assembly.to_string(), "Assembly {subject_code}.{class_code}");
+    assert_eq!(
+        assembly.unrender("Assembly PC.1"),
+        Some(Unrendered {
+            subject_code: Some("PC".to_string()),
+            class_code: Some("1".to_string()),
+            ..Unrendered::default()
+        })
+    );
+    assert_eq!(assembly.unrender("PC.1"), None);
+
+    // Values left out when rendering come back as `None`
+    let title: Template = "{subject} ({class_code})".parse().unwrap();
+    let commerce = |class_code: Option<&str>| Unrendered {
+        subject: Some("Commerce".to_string()),
+        class_code: class_code.map(str::to_string),
+        ..Unrendered::default()
+    };
+    assert_eq!(title.unrender("Commerce (1)"), Some(commerce(Some("1"))));
+    assert_eq!(title.unrender("Commerce"), Some(commerce(None)));
+    let info: Template = "{room} with {teacher}".parse().unwrap();
+    let room = Some(Unrendered {
+        room: Some("H1".to_string()),
+        ..Unrendered::default()
+    });
+    assert_eq!(info.unrender("H1 with J Smith"), room);
+    assert_eq!(info.unrender("H1"), room);
+    assert_eq!(info.unrender(""), Some(Unrendered::default()));
+    assert_eq!(
+        "{{{period}}}".parse::<Template>().unwrap().to_string(),
+        "{{{period}}}"
+    );
+
+    assert!("{teacher".parse::<Template>().is_err());
+    assert!("{name}".parse::<Template>().is_err());
+    assert!("}".parse::<Template>().is_err());
+}
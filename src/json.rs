@@ -0,0 +1,120 @@
+use crate::types::{StudentIdentity, Timetable, MAX_WEEKS};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Bumped whenever the serialized shape of `Timetable` changes
+pub(crate) const SCHEMA_VERSION: u32 = 6;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) struct TimetableFile {
+    pub(crate) schema_version: u32,
+    pub(crate) student: StudentIdentity,
+    pub(crate) timetable: Timetable,
+}
+
+impl TimetableFile {
+    pub(crate) fn new(student: StudentIdentity, timetable: Timetable) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            student,
+            timetable,
+        }
+    }
+}
+
+pub(crate) fn write_timetable_json(path: &Path, file: &TimetableFile) -> anyhow::Result<()> {
+    let json = serde_json::to_string_pretty(file)?;
+    std::fs::write(path, json)?;
+
+    Ok(())
+}
+
+/// Writes a timetable with the colour of every event title,
+/// which can still be read back as a plain `TimetableFile`
+pub(crate) fn write_export_json(
+    path: &Path,
+    file: &TimetableFile,
+    colours: &BTreeMap<String, String>,
+) -> anyhow::Result<()> {
+    #[derive(Serialize)]
+    struct Export<'a> {
+        #[serde(flatten)]
+        file: &'a TimetableFile,
+        colours: &'a BTreeMap<String, String>,
+    }
+
+    let json = serde_json::to_string_pretty(&Export { file, colours })?;
+    std::fs::write(path, json)?;
+
+    Ok(())
+}
+
+pub(crate) fn read_timetable_json(path: &Path) -> anyhow::Result<TimetableFile> {
+    let json = std::fs::read_to_string(path)?;
+
+    // Check the version before deserializing the rest,
+    // so an old file gives a useful error instead of a missing field
+    #[derive(Deserialize)]
+    struct Version {
+        schema_version: u32,
+    }
+    let Version { schema_version } = serde_json::from_str(&json)?;
+    anyhow::ensure!(
+        schema_version == SCHEMA_VERSION,
+        "{} has schema version {}, expected {}",
+        path.display(),
+        schema_version,
+        SCHEMA_VERSION
+    );
+
+    let file: TimetableFile = serde_json::from_str(&json)?;
+    anyhow::ensure!(
+        file.timetable.weeks.len() <= MAX_WEEKS,
+        "{} has {} weeks, but there can only be {}",
+        path.display(),
+        file.timetable.weeks.len(),
+        MAX_WEEKS
+    );
+
+    Ok(file)
+}
+
+#[cfg(test)]
+#[test]
+fn test_round_trip() {
+    use crate::types::{Day, DayOfWeek, Lesson, Period, Week};
+
+    let mut week_a = Week::default();
+    week_a.days.insert(
+        DayOfWeek::Monday,
+        Day::from_lessons([
+            (Period::P0, Lesson::AbsentPeriod),
+            (
+                Period::P1,
+                Lesson::class("COM")
+                    .named("Commerce")
+                    .in_faculty("HSIE")
+                    .in_room("H1")
+                    .taught_by("J", "Smith"),
+            ),
+            (Period::Recess, Lesson::Recess),
+            (Period::P2, Lesson::FreePeriod),
+        ])
+        .unwrap(),
+    );
+
+    let file = TimetableFile::new(
+        StudentIdentity {
+            first: "Jane".to_string(),
+            last: "Doe".to_string(),
+            id: "123".to_string(),
+        },
+        Timetable {
+            weeks: vec![week_a, Week::default()],
+        },
+    );
+
+    let json = serde_json::to_string(&file).unwrap();
+    assert_eq!(serde_json::from_str::<TimetableFile>(&json).unwrap(), file);
+}
@@ -0,0 +1,110 @@
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+
+pub(crate) use self::binary::to_binary;
+pub(crate) use self::xml::{from_xml, to_xml};
+
+mod binary;
+mod xml;
+
+pub(crate) type Dict = BTreeMap<String, Value>;
+
+/// A property list value, which is what `.timetable` files are made of
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Value {
+    Dict(Dict),
+    Array(Vec<Value>),
+    String(String),
+    Real(f64),
+    Integer(i64),
+    /// Only whole seconds are kept
+    Date(DateTime<Utc>),
+    Bool(bool),
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::String(value.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::String(value)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Real(value)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Integer(value)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl From<DateTime<Utc>> for Value {
+    fn from(value: DateTime<Utc>) -> Self {
+        Value::Date(value)
+    }
+}
+
+impl From<Dict> for Value {
+    fn from(value: Dict) -> Self {
+        Value::Dict(value)
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(value: Vec<Value>) -> Self {
+        Value::Array(value)
+    }
+}
+
+impl Value {
+    pub(crate) fn as_dict(&self) -> Option<&Dict> {
+        match self {
+            Value::Dict(dict) => Some(dict),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(array) => Some(array),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(string) => Some(string),
+            _ => None,
+        }
+    }
+
+    /// Integers count as reals, since other writers don't always keep them apart
+    pub(crate) fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Real(real) => Some(*real),
+            Value::Integer(integer) => Some(*integer as f64),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Integer(integer) => Some(*integer),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use super::Value;
+use chrono::{TimeZone, Utc};
+use std::collections::HashMap;
+
+const MAGIC: &[u8] = b"bplist00";
+
+/// Writes a binary (`bplist00`) property list.
+///
+/// Strings, numbers and dates are only written once however often they appear,
+/// which is what keeps thousands of events with the same keys small.
+pub(crate) fn to_binary(value: &Value) -> Vec<u8> {
+    let mut writer = Writer::default();
+    let top = writer.flatten(value);
+
+    let ref_size = int_size(writer.objects.len() as u64 - 1);
+    let mut bplist = MAGIC.to_vec();
+    let mut offsets = Vec::with_capacity(writer.objects.len());
+
+    for object in &writer.objects {
+        offsets.push(bplist.len() as u64);
+        match object {
+            Object::Scalar(bytes) => bplist.extend_from_slice(bytes),
+            Object::Array(refs) => {
+                write_marker(&mut bplist, 0xa0, refs.len());
+                write_refs(&mut bplist, refs, ref_size);
+            }
+            Object::Dict(keys, values) => {
+                write_marker(&mut bplist, 0xd0, keys.len());
+                write_refs(&mut bplist, keys, ref_size);
+                write_refs(&mut bplist, values, ref_size);
+            }
+        }
+    }
+
+    let offset_table_offset = bplist.len() as u64;
+    let offset_size = int_size(offset_table_offset);
+    for offset in offsets {
+        write_sized(&mut bplist, offset, offset_size);
+    }
+
+    // Trailer
+    bplist.extend_from_slice(&[0; 6]);
+    bplist.push(offset_size as u8);
+    bplist.push(ref_size as u8);
+    bplist.extend_from_slice(&(writer.objects.len() as u64).to_be_bytes());
+    bplist.extend_from_slice(&(top as u64).to_be_bytes());
+    bplist.extend_from_slice(&offset_table_offset.to_be_bytes());
+
+    bplist
+}
+
+enum Object {
+    /// Already encoded
+    Scalar(Vec<u8>),
+    Array(Vec<usize>),
+    Dict(Vec<usize>, Vec<usize>),
+}
+
+#[derive(Default)]
+struct Writer {
+    objects: Vec<Object>,
+    /// Index of every scalar by its encoding
+    scalars: HashMap<Vec<u8>, usize>,
+}
+
+impl Writer {
+    /// Adds a value and everything in it, returning its index
+    fn flatten(&mut self, value: &Value) -> usize {
+        match value {
+            Value::Dict(dict) => {
+                let idx = self.reserve();
+                let keys = dict
+                    .keys()
+                    .map(|key| self.scalar(encode_string(key)))
+                    .collect();
+                let values = dict.values().map(|value| self.flatten(value)).collect();
+                self.objects[idx] = Object::Dict(keys, values);
+                idx
+            }
+            Value::Array(array) => {
+                let idx = self.reserve();
+                let values = array.iter().map(|value| self.flatten(value)).collect();
+                self.objects[idx] = Object::Array(values);
+                idx
+            }
+            Value::String(string) => self.scalar(encode_string(string)),
+            Value::Real(real) => {
+                let mut bytes = vec![0x23];
+                bytes.extend_from_slice(&real.to_be_bytes());
+                self.scalar(bytes)
+            }
+            Value::Integer(integer) => self.scalar(encode_int(*integer)),
+            Value::Date(date) => {
+                // Seconds since the start of 2001
+                let epoch = Utc.with_ymd_and_hms(2001, 1, 1, 0, 0, 0).unwrap();
+                let seconds = (*date - epoch).num_seconds() as f64;
+                let mut bytes = vec![0x33];
+                bytes.extend_from_slice(&seconds.to_be_bytes());
+                self.scalar(bytes)
+            }
+            Value::Bool(false) => self.scalar(vec![0x08]),
+            Value::Bool(true) => self.scalar(vec![0x09]),
+        }
+    }
+
+    fn reserve(&mut self) -> usize {
+        self.objects.push(Object::Array(Vec::new()));
+        self.objects.len() - 1
+    }
+
+    fn scalar(&mut self, bytes: Vec<u8>) -> usize {
+        if let Some(idx) = self.scalars.get(&bytes) {
+            return *idx;
+        }
+
+        let idx = self.objects.len();
+        self.scalars.insert(bytes.clone(), idx);
+        self.objects.push(Object::Scalar(bytes));
+        idx
+    }
+}
+
+/// ASCII strings are written as bytes and anything else as UTF-16
+fn encode_string(string: &str) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    if string.is_ascii() {
+        write_marker(&mut bytes, 0x50, string.len());
+        bytes.extend_from_slice(string.as_bytes());
+    } else {
+        let units: Vec<u16> = string.encode_utf16().collect();
+        write_marker(&mut bytes, 0x60, units.len());
+        for unit in units {
+            bytes.extend_from_slice(&unit.to_be_bytes());
+        }
+    }
+    bytes
+}
+
+/// Integers under 8 bytes are unsigned, so negative ones always take 8
+fn encode_int(integer: i64) -> Vec<u8> {
+    let size = match u64::try_from(integer) {
+        Ok(integer) => int_size(integer),
+        Err(_) => 8,
+    };
+
+    let mut bytes = vec![0x10 | size.trailing_zeros() as u8];
+    write_sized(&mut bytes, integer as u64, size);
+    bytes
+}
+
+/// Writes an object's type and its length, which follows as an integer when it doesn't fit
+fn write_marker(bytes: &mut Vec<u8>, marker: u8, len: usize) {
+    if len < 0xf {
+        bytes.push(marker | len as u8);
+    } else {
+        bytes.push(marker | 0xf);
+        bytes.extend(encode_int(len as i64));
+    }
+}
+
+fn write_refs(bytes: &mut Vec<u8>, refs: &[usize], ref_size: usize) {
+    for idx in refs {
+        write_sized(bytes, *idx as u64, ref_size);
+    }
+}
+
+/// Writes the lowest `size` bytes of a number, big endian
+fn write_sized(bytes: &mut Vec<u8>, value: u64, size: usize) {
+    bytes.extend_from_slice(&value.to_be_bytes()[8 - size..]);
+}
+
+/// Fewest bytes out of 1, 2, 4 and 8 which fit a number
+fn int_size(value: u64) -> usize {
+    match value {
+        0..=0xff => 1,
+        0x100..=0xffff => 2,
+        0x1_0000..=0xffff_ffff => 4,
+        _ => 8,
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_to_binary() {
+    use super::Dict;
+
+    let hex = |value: &Value| {
+        to_binary(value)
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>()
+    };
+
+    // Compared against Python's plistlib
+    let mut dict = Dict::new();
+    dict.insert("a".to_string(), Value::Integer(1));
+    assert_eq!(
+        hex(&Value::Dict(dict)),
+        "62706c6973743030d1010251611001080b0d00000000000001010000000000000003\
+         0000000000000000000000000000000f"
+    );
+
+    let mut dict = Dict::new();
+    dict.insert(
+        "b".to_string(),
+        Value::Array(vec![Value::Real(1.5), Value::from("é"), Value::Bool(true)]),
+    );
+    dict.insert("a".to_string(), Value::from("x".repeat(20)));
+    assert_eq!(
+        hex(&Value::Dict(dict)),
+        "62706c6973743030d201020304516151625f10147878787878787878787878787878\
+         787878787878a3050607233ff80000000000006100e909080d0f11282c3538000000\
+         0000000101000000000000000800000000000000000000000000000039"
+    );
+}
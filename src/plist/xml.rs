@@ -0,0 +1,361 @@
+use super::{Dict, Value};
+use chrono::{NaiveDateTime, TimeZone, Utc};
+
+const HEADER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+"#;
+
+/// Writes an XML property list in the same layout as Apple's tools
+pub(crate) fn to_xml(value: &Value) -> String {
+    let mut xml = String::from(HEADER);
+    write_value(&mut xml, value, 0);
+    xml += "</plist>\n";
+    xml
+}
+
+fn write_value(xml: &mut String, value: &Value, depth: usize) {
+    let indent = "\t".repeat(depth);
+
+    match value {
+        Value::Dict(dict) if dict.is_empty() => *xml += &format!("{indent}<dict/>\n"),
+        Value::Dict(dict) => {
+            *xml += &format!("{indent}<dict>\n");
+            for (key, value) in dict {
+                *xml += &format!("{indent}\t<key>{}</key>\n", escape(key));
+                write_value(xml, value, depth + 1);
+            }
+            *xml += &format!("{indent}</dict>\n");
+        }
+        Value::Array(array) if array.is_empty() => *xml += &format!("{indent}<array/>\n"),
+        Value::Array(array) => {
+            *xml += &format!("{indent}<array>\n");
+            for value in array {
+                write_value(xml, value, depth + 1);
+            }
+            *xml += &format!("{indent}</array>\n");
+        }
+        Value::String(string) => *xml += &format!("{indent}<string>{}</string>\n", escape(string)),
+        Value::Real(real) => *xml += &format!("{indent}<real>{}</real>\n", format_real(*real)),
+        Value::Integer(integer) => *xml += &format!("{indent}<integer>{integer}</integer>\n"),
+        Value::Date(date) => {
+            *xml += &format!(
+                "{indent}<date>{}</date>\n",
+                date.format("%Y-%m-%dT%H:%M:%SZ")
+            )
+        }
+        Value::Bool(true) => *xml += &format!("{indent}<true/>\n"),
+        Value::Bool(false) => *xml += &format!("{indent}<false/>\n"),
+    }
+}
+
+fn format_real(real: f64) -> String {
+    if real.is_nan() {
+        "nan".to_string()
+    } else if real.is_infinite() {
+        if real > 0.0 { "+infinity" } else { "-infinity" }.to_string()
+    } else {
+        real.to_string()
+    }
+}
+
+/// Escapes text for an element's content.
+/// Control characters can't appear in XML 1.0 at all, even escaped, so they're left out.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() => {}
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Reads an XML property list
+pub(crate) fn from_xml(text: &str) -> anyhow::Result<Value> {
+    let mut reader = Reader { text, pos: 0 };
+
+    reader.skip_prolog()?;
+    let (name, empty) = reader.open_tag()?;
+    anyhow::ensure!(name == "plist" && !empty, "expected <plist>");
+
+    let value = reader.value()?;
+    reader.close_tag("plist")?;
+
+    Ok(value)
+}
+
+struct Reader<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn rest(&self) -> &str {
+        &self.text[self.pos..]
+    }
+
+    fn error(&self, message: &str) -> anyhow::Error {
+        let line = self.text[..self.pos].matches('\n').count() + 1;
+        anyhow::anyhow!("{} on line {}", message, line)
+    }
+
+    /// Skips whitespace and comments
+    fn skip_misc(&mut self) -> anyhow::Result<()> {
+        loop {
+            let trimmed = self.rest().trim_start();
+            self.pos = self.text.len() - trimmed.len();
+
+            if self.rest().starts_with("<!--") {
+                let end = self
+                    .rest()
+                    .find("-->")
+                    .ok_or_else(|| self.error("unclosed comment"))?;
+                self.pos += end + "-->".len();
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Skips the XML declaration and doctype
+    fn skip_prolog(&mut self) -> anyhow::Result<()> {
+        loop {
+            self.skip_misc()?;
+            if self.rest().starts_with("<?") || self.rest().starts_with("<!") {
+                let end = self
+                    .rest()
+                    .find('>')
+                    .ok_or_else(|| self.error("unclosed declaration"))?;
+                self.pos += end + 1;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Reads a tag like `<dict>` or `<true/>`, returning its name and whether it's empty
+    fn open_tag(&mut self) -> anyhow::Result<(&str, bool)> {
+        self.skip_misc()?;
+        if !self.rest().starts_with('<') || self.rest().starts_with("</") {
+            return Err(self.error("expected an element"));
+        }
+
+        let end = self
+            .rest()
+            .find('>')
+            .ok_or_else(|| self.error("unclosed tag"))?;
+        let tag = &self.text[self.pos + 1..self.pos + end];
+        self.pos += end + 1;
+
+        let (tag, empty) = match tag.strip_suffix('/') {
+            Some(tag) => (tag, true),
+            None => (tag, false),
+        };
+        // Attributes like plist's version don't matter
+        let name = tag.split_whitespace().next().unwrap_or_default();
+
+        Ok((name, empty))
+    }
+
+    fn close_tag(&mut self, name: &str) -> anyhow::Result<()> {
+        self.skip_misc()?;
+        let tag = format!("</{name}>");
+        if !self.rest().starts_with(&tag) {
+            return Err(self.error(&format!("expected {tag}")));
+        }
+        self.pos += tag.len();
+
+        Ok(())
+    }
+
+    /// Reads the text of an element up to its closing tag
+    fn text(&mut self, name: &str) -> anyhow::Result<String> {
+        let tag = format!("</{name}>");
+        let end = self
+            .rest()
+            .find(&tag)
+            .ok_or_else(|| self.error(&format!("expected {tag}")))?;
+        let text = unescape(&self.text[self.pos..self.pos + end])
+            .map_err(|e| self.error(&e.to_string()))?;
+        self.pos += end + tag.len();
+
+        Ok(text)
+    }
+
+    fn value(&mut self) -> anyhow::Result<Value> {
+        let (name, empty) = self.open_tag()?;
+        let name = name.to_string();
+
+        let value = match (name.as_str(), empty) {
+            ("dict", true) => Value::Dict(Dict::new()),
+            ("dict", false) => {
+                let mut dict = Dict::new();
+                loop {
+                    self.skip_misc()?;
+                    if self.rest().starts_with("</dict>") {
+                        self.pos += "</dict>".len();
+                        break;
+                    }
+
+                    let (tag, empty) = self.open_tag()?;
+                    if tag != "key" || empty {
+                        return Err(self.error("expected <key>"));
+                    }
+                    let key = self.text("key")?;
+                    let value = self.value()?;
+                    dict.insert(key, value);
+                }
+                Value::Dict(dict)
+            }
+            ("array", true) => Value::Array(Vec::new()),
+            ("array", false) => {
+                let mut array = Vec::new();
+                loop {
+                    self.skip_misc()?;
+                    if self.rest().starts_with("</array>") {
+                        self.pos += "</array>".len();
+                        break;
+                    }
+                    array.push(self.value()?);
+                }
+                Value::Array(array)
+            }
+            ("string", true) => Value::String(String::new()),
+            ("string", false) => Value::String(self.text("string")?),
+            ("real", false) => {
+                let text = self.text("real")?;
+                Value::Real(
+                    parse_real(text.trim())
+                        .ok_or_else(|| self.error(&format!("{:?} isn't a real", text)))?,
+                )
+            }
+            ("integer", false) => {
+                let text = self.text("integer")?;
+                Value::Integer(
+                    text.trim()
+                        .parse()
+                        .map_err(|_| self.error(&format!("{:?} isn't an integer", text)))?,
+                )
+            }
+            ("date", false) => {
+                let text = self.text("date")?;
+                let date = NaiveDateTime::parse_from_str(text.trim(), "%Y-%m-%dT%H:%M:%SZ")
+                    .map_err(|_| self.error(&format!("{:?} isn't a date", text)))?;
+                Value::Date(Utc.from_utc_datetime(&date))
+            }
+            ("true", true) => Value::Bool(true),
+            ("false", true) => Value::Bool(false),
+            _ => return Err(self.error(&format!("unexpected <{name}>"))),
+        };
+
+        Ok(value)
+    }
+}
+
+fn parse_real(text: &str) -> Option<f64> {
+    match text {
+        "nan" => Some(f64::NAN),
+        "+infinity" | "infinity" | "inf" => Some(f64::INFINITY),
+        "-infinity" | "-inf" => Some(f64::NEG_INFINITY),
+        _ => text.parse().ok(),
+    }
+}
+
+fn unescape(text: &str) -> anyhow::Result<String> {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let end = rest
+            .find(';')
+            .ok_or_else(|| anyhow::anyhow!("unterminated entity"))?;
+        let entity = &rest[1..end];
+        let c = match entity {
+            "amp" => '&',
+            "lt" => '<',
+            "gt" => '>',
+            "quot" => '"',
+            "apos" => '\'',
+            _ => {
+                let code = match entity.strip_prefix("#x") {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => entity.strip_prefix('#').and_then(|dec| dec.parse().ok()),
+                };
+                code.and_then(char::from_u32)
+                    .ok_or_else(|| anyhow::anyhow!("unknown entity &{};", entity))?
+            }
+        };
+        unescaped.push(c);
+        rest = &rest[end + 1..];
+    }
+    unescaped.push_str(rest);
+
+    Ok(unescaped)
+}
+
+#[cfg(test)]
+#[test]
+fn test_escape() {
+    let mut dict = Dict::new();
+    dict.insert("<key>".to_string(), Value::from("Maths & <Science>\u{7}"));
+    dict.insert("empty".to_string(), Value::Array(Vec::new()));
+
+    let xml = to_xml(&Value::Dict(dict));
+    assert_eq!(
+        xml.strip_prefix(HEADER).unwrap(),
+        "<dict>\n\
+         \t<key>&lt;key&gt;</key>\n\
+         \t<string>Maths &amp; &lt;Science&gt;</string>\n\
+         \t<key>empty</key>\n\
+         \t<array/>\n\
+         </dict>\n\
+         </plist>\n"
+    );
+}
+
+#[cfg(test)]
+#[test]
+fn test_round_trip() {
+    let mut dict = Dict::new();
+    dict.insert(
+        "string".to_string(),
+        Value::from("Maths & <Science> \"Pre\""),
+    );
+    dict.insert("empty".to_string(), Value::from(""));
+    dict.insert("real".to_string(), Value::Real(0.1));
+    dict.insert("integer".to_string(), Value::Integer(-3));
+    dict.insert(
+        "date".to_string(),
+        Value::Date("2023-01-30T08:55:00Z".parse().unwrap()),
+    );
+    dict.insert(
+        "array".to_string(),
+        Value::Array(vec![
+            Value::Bool(true),
+            Value::Bool(false),
+            Value::Dict(Dict::new()),
+        ]),
+    );
+    let value = Value::Dict(dict);
+
+    assert_eq!(from_xml(&to_xml(&value)).unwrap(), value);
+
+    // Written by something else
+    assert_eq!(
+        from_xml(
+            "<?xml version=\"1.0\"?><!-- comment --><plist version=\"1.0\">\
+             <array><string>&#x41;&#66;&apos;</string><string/></array></plist>"
+        )
+        .unwrap(),
+        Value::Array(vec![Value::from("AB'"), Value::from("")])
+    );
+}
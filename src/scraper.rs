@@ -1,9 +1,21 @@
-use crate::types::{DayOfWeek, Lesson, StudentIdentity, Subject, Teacher, Timetable, Week};
+use crate::types::{
+    Day, DayOfWeek, Lesson, StudentIdentity, Subject, Teacher, Timetable, Week, MAX_WEEKS,
+};
 use reqwest::blocking::Client;
 use select::document::Document;
 use select::node::Node;
 use select::predicate::{Attr, Class, Name};
-use std::mem;
+use std::collections::BTreeMap;
+
+pub(crate) use self::error::{ScrapeError, ScrapeErrorKind};
+pub(crate) use self::layout::LayoutProfile;
+use self::layout::SlotKind;
+use self::rules::Resolved;
+pub(crate) use self::rules::{LessonColour, LessonRules};
+
+mod error;
+mod layout;
+mod rules;
 
 pub(crate) fn scrape_student_identities(
     client: &Client,
@@ -18,46 +30,85 @@ pub(crate) fn scrape_student_identities(
             .as_str(),
     );
 
-    let student_entries = document.find(Attr("id", "student-entries")).next().unwrap();
+    let student_entries = document
+        .find(Attr("id", "student-entries"))
+        .next()
+        .ok_or_else(|| {
+            ScrapeError::without_html(ScrapeErrorKind::MissingElement("#student-entries"))
+        })?;
     let student_identities = student_entries
         .find(Name("a"))
         .map(|n| scrape_student_identity(n, timetable_uuid))
-        .collect();
+        .collect::<Result<_, _>>()?;
 
     Ok(student_identities)
 }
 
-fn scrape_student_identity(node: Node<'_>, timetable_uuid: &str) -> StudentIdentity {
-    let name = node.find(Name("span")).next().unwrap().text();
-    let (first, last) = name.split_once(' ').unwrap();
+fn scrape_student_identity(
+    node: Node<'_>,
+    timetable_uuid: &str,
+) -> Result<StudentIdentity, ScrapeError> {
+    let name = node
+        .find(Name("span"))
+        .next()
+        .ok_or_else(|| ScrapeError::new(ScrapeErrorKind::MissingElement("name"), node))?
+        .text();
+    let (first, last) = name.split_once(' ').ok_or_else(|| {
+        ScrapeError::new(
+            ScrapeErrorKind::Malformed {
+                what: "name",
+                text: name.clone(),
+            },
+            node,
+        )
+    })?;
     let id = node
         .attr("href")
-        .unwrap()
-        .strip_prefix(&format!("/{}/", timetable_uuid))
-        .unwrap();
+        .and_then(|href| href.strip_prefix(&format!("/{}/", timetable_uuid)))
+        .ok_or_else(|| ScrapeError::new(ScrapeErrorKind::MissingElement("student link"), node))?;
 
-    StudentIdentity {
+    Ok(StudentIdentity {
         first: first.to_string(),
         last: last.to_string(),
         id: id.to_string(),
-    }
+    })
 }
 
-pub(crate) fn scrape_timetable_page(text: &str) -> Timetable {
+pub(crate) fn scrape_timetable_page(
+    text: &str,
+    layout: &LayoutProfile,
+    rules: &LessonRules,
+) -> Result<Timetable, ScrapeError> {
     let document = Document::from(text);
 
-    let mut tables = document.find(Class("table"));
+    // There is a table for every week of the cycle
+    let tables = document.find(Class("table")).count();
+    if tables > MAX_WEEKS {
+        return Err(ScrapeError::without_html(ScrapeErrorKind::WeekCount(
+            tables,
+        )));
+    }
 
-    let week_a = tables.next().unwrap();
-    let week_b = tables.next().unwrap();
+    let weeks = document
+        .find(Class("table"))
+        .enumerate()
+        .map(|(idx, table)| scrape_week(table, layout, rules).map_err(|e| e.with_week(idx)))
+        .collect::<Result<Vec<_>, _>>()?;
 
-    Timetable {
-        week_a: scrape_week(week_a),
-        week_b: scrape_week(week_b),
+    if weeks.is_empty() {
+        return Err(ScrapeError::without_html(ScrapeErrorKind::MissingElement(
+            "week table",
+        )));
     }
+
+    Ok(Timetable { weeks })
 }
 
-fn scrape_week(node: Node<'_>) -> Week {
+fn scrape_week(
+    node: Node<'_>,
+    layout: &LayoutProfile,
+    rules: &LessonRules,
+) -> Result<Week, ScrapeError> {
     let mut week = Week::default();
 
     const DAYS_OF_WEEK: [DayOfWeek; 5] = [
@@ -70,112 +121,141 @@ fn scrape_week(node: Node<'_>) -> Week {
 
     // Lessons are stored in table in rows with each column representing a day of the week.
     // Go across each row and add the lessons with the correct day of the week.
-    for (i, lesson) in node.find(Name("td")).enumerate() {
-        let lesson = scrape_lesson(lesson);
-
+    let mut rows: BTreeMap<DayOfWeek, Vec<(Node<'_>, Option<Lesson>)>> = BTreeMap::new();
+    for (i, cell) in node.find(Name("td")).enumerate() {
         let day_of_week = DAYS_OF_WEEK[i % 5];
-        let day = week.days.entry(day_of_week).or_default();
-        day.lessons.push(lesson);
-    }
+        let row = i / 5;
 
-    for (day_of_week, day) in &mut week.days {
-        // 0, 1, 2, Assembly, Break/PC, R, 3, 4, L1, L2, 5, 6, 7, 8
+        let lesson =
+            scrape_lesson(cell, rules).map_err(|e| e.with_day(day_of_week).with_row(row))?;
 
-        // Break/PC row is always empty;
-        // it carries no information so we remove it
-        assert_eq!(day.lessons.remove(4), Lesson::FreePeriod);
-        // Ends up with: 0, 1, 2, Assembly, R, 3, 4, L1, L2, 5, 6, 7, 8
-
-        assert_eq!(
-            mem::replace(&mut day.lessons[4], Lesson::Recess),
-            Lesson::FreePeriod
-        );
-
-        // Some students have lessons during lunch,
-        // so we check that lunch lessons are empty before replacing them
-        if day.lessons[7] == Lesson::FreePeriod {
-            day.lessons[7] = Lesson::Lunch1;
-        }
-        if day.lessons[8] == Lesson::FreePeriod {
-            day.lessons[8] = Lesson::Lunch2;
-        }
+        rows.entry(day_of_week)
+            .or_default()
+            .push((cell, Some(lesson)));
+    }
 
-        // There is only assembly on Wednesday
-        if *day_of_week != DayOfWeek::Wednesday {
-            assert_eq!(day.lessons.remove(3), Lesson::FreePeriod);
-        }
-        // Changes non-wednesdays to 0, 1, 2, R, 3, 4, L1, L2, 5, 6, 7, 8
-
-        // the website shows period 4 on a Wednesday before lunch,
-        // when it actually happens after
-        if *day_of_week == DayOfWeek::Wednesday {
-            let period_4 = day.lessons.remove(6);
-            // Ends up with: 0, 1, 2, Assembly, R, 3, L1, L2, 5, 6, 7, 8
-            day.lessons.insert(8, period_4);
+    for (day_of_week, mut rows) in rows {
+        if rows.len() != layout.rows {
+            return Err(ScrapeError::new(
+                ScrapeErrorKind::RowCount {
+                    expected: layout.rows,
+                    found: rows.len(),
+                },
+                node,
+            )
+            .with_day(day_of_week));
         }
 
-        // Mark first period as absent if it is empty
-        if day.lessons[0] == Lesson::FreePeriod {
-            day.lessons[0] = Lesson::AbsentPeriod;
+        let expected_empty = |cell, row| {
+            ScrapeError::new(ScrapeErrorKind::ExpectedEmpty, cell)
+                .with_day(day_of_week)
+                .with_row(row)
         };
 
-        // Mark consecutive sequences of free periods at the end of the day as absent
-        // TODO: Better handling, probably want to show frees until regular end times
-        let last_class_idx = day
-            .lessons
-            .iter()
-            .rposition(|lesson| *lesson != Lesson::FreePeriod)
-            .unwrap();
-
-        let absent_class_idxs = last_class_idx + 1..day.lessons.len();
-        for idx in absent_class_idxs {
-            day.lessons[idx] = Lesson::AbsentPeriod;
+        let slots = layout.day(day_of_week);
+        let mut lessons = Vec::with_capacity(slots.len());
+
+        for slot in slots {
+            let (cell, lesson) = &mut rows[slot.row];
+            let lesson = lesson.take().unwrap();
+
+            let lesson = match (slot.kind, lesson) {
+                (SlotKind::Class, lesson) => lesson,
+                (SlotKind::Recess, Lesson::FreePeriod) => Lesson::Recess,
+                (SlotKind::Recess, _) => return Err(expected_empty(*cell, slot.row)),
+                // Some students have lessons during lunch,
+                // so we check that lunch lessons are empty before replacing them
+                (SlotKind::Lunch1, Lesson::FreePeriod) => Lesson::Lunch1,
+                (SlotKind::Lunch2, Lesson::FreePeriod) => Lesson::Lunch2,
+                (SlotKind::Lunch1 | SlotKind::Lunch2, lesson) => lesson,
+            };
+            lessons.push((slot.period, lesson));
         }
+
+        // Rows which aren't part of the day carry no information so we drop them,
+        // but only if they're empty as expected
+        for (row, (cell, lesson)) in rows.iter().enumerate() {
+            if lesson
+                .as_ref()
+                .is_some_and(|lesson| *lesson != Lesson::FreePeriod)
+            {
+                return Err(expected_empty(*cell, row));
+            }
+        }
+
+        // Free periods are kept as they are,
+        // since whether they're shown depends on the config when generating
+        // The layout never lists a period twice
+        let day = Day::from_lessons(lessons).unwrap();
+        week.days.insert(day_of_week, day);
     }
 
-    week
+    Ok(week)
 }
 
-fn scrape_lesson(node: Node<'_>) -> Lesson {
+fn scrape_lesson(node: Node<'_>, rules: &LessonRules) -> Result<Lesson, ScrapeError> {
     if node.children().count() == 0 {
-        return Lesson::FreePeriod;
+        return Ok(Lesson::FreePeriod);
     }
 
-    let subject = node.find(Name("strong")).next().unwrap();
+    let missing = |element| ScrapeError::new(ScrapeErrorKind::MissingElement(element), node);
+
+    let subject = node
+        .find(Name("strong"))
+        .next()
+        .ok_or_else(|| missing("subject"))?;
     let mut spans = node.find(Name("span"));
     spans.next(); // Skip first span which is the period number
-    let teacher = spans.next().unwrap();
-    let room = spans.next().unwrap();
-    let lesson_code = node.find(Name("small")).next().unwrap().text();
-    let (subject_code, class_code) = lesson_code.split_once(' ').unwrap();
-    // Handle sports i.e. "SPTTennis 1"
-    let subject_code = subject_code.strip_prefix("SPT").unwrap_or(subject_code);
-
-    Lesson::Present {
-        subject: scrape_subject(subject),
+    let teacher = spans.next().ok_or_else(|| missing("teacher"))?;
+    let room = spans.next().ok_or_else(|| missing("room"))?;
+    let lesson_code = node
+        .find(Name("small"))
+        .next()
+        .ok_or_else(|| missing("lesson code"))?
+        .text();
+    let (subject_code, class_code) = lesson_code.split_once(' ').ok_or_else(|| {
+        ScrapeError::new(
+            ScrapeErrorKind::Malformed {
+                what: "lesson code",
+                text: lesson_code.clone(),
+            },
+            node,
+        )
+    })?;
+    let resolved = rules.resolve(&subject.text(), subject_code);
+
+    Ok(Lesson::Present {
+        subject: scrape_subject(subject, &resolved)?,
         teacher: scrape_teacher(teacher),
         room: scrape_room(room),
-        subject_code: subject_code.to_string(),
+        subject_code: resolved.subject_code.to_string(),
         class_code: class_code.to_string(),
-    }
+    })
 }
 
-fn scrape_subject(node: Node<'_>) -> Subject {
+fn scrape_subject(node: Node<'_>, resolved: &Resolved<'_>) -> Result<Subject, ScrapeError> {
     // i.e. "HSIE | Commerce Yr9"
     let text = node.text();
 
-    if text == "Pastoral Care Past_Car" {
-        return Subject {
-            name: "Assembly".to_string(),
-            faculty: "Pastoral Care".to_string(),
-        };
-    } else if text == "Sport Sport" {
-        return Subject {
-            name: "Sport".to_string(),
-            faculty: "Phys.Ed".to_string(),
-        };
+    // Special lessons don't always follow the usual format
+    if let (Some(name), Some(faculty)) = (resolved.name, resolved.faculty) {
+        return Ok(Subject {
+            name: name.to_string(),
+            faculty: faculty.to_string(),
+            kind: resolved.kind,
+        });
     }
 
+    let malformed = || {
+        ScrapeError::new(
+            ScrapeErrorKind::Malformed {
+                what: "subject",
+                text: text.clone(),
+            },
+            node,
+        )
+    };
+
     let (name, faculty) = if text.contains('|') {
         let mut components = text.split('|');
         let faculty = components.next().unwrap();
@@ -183,21 +263,22 @@ fn scrape_subject(node: Node<'_>) -> Subject {
         let name = components.next().unwrap_or(faculty);
 
         // Strip year group off
-        let last_space = name.rfind(' ').unwrap();
+        let last_space = name.rfind(' ').ok_or_else(malformed)?;
         let name = &name[..last_space];
 
         (name, faculty)
     } else {
-        let last_space = text.rfind(' ').unwrap();
+        let last_space = text.rfind(' ').ok_or_else(malformed)?;
         let text = &text[..last_space];
 
         (text, text)
     };
 
-    Subject {
-        name: name.trim().to_string(),
-        faculty: faculty.trim().to_string(),
-    }
+    Ok(Subject {
+        name: resolved.name.unwrap_or(name).trim().to_string(),
+        faculty: resolved.faculty.unwrap_or(faculty).trim().to_string(),
+        kind: resolved.kind,
+    })
 }
 
 fn scrape_teacher(node: Node<'_>) -> Option<Teacher> {
@@ -226,3 +307,172 @@ fn scrape_room(node: Node<'_>) -> Option<String> {
 
     Some(text)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{LessonKind, Period};
+
+    const LESSON: &str = "<td><span>1</span><strong>HSIE | Commerce Yr9</strong><span>Smith J</span><span>H1</span><small>COM 1</small></td>";
+
+    /// Builds a week table where `lesson_at(row, column)` decides which cells have a lesson
+    fn week_table(lesson_at: impl Fn(usize, usize) -> bool) -> String {
+        let mut html = String::from(r#"<table class="table">"#);
+        for row in 0..LayoutProfile::default().rows {
+            html += "<tr>";
+            for column in 0..5 {
+                html += if lesson_at(row, column) {
+                    LESSON
+                } else {
+                    "<td></td>"
+                };
+            }
+            html += "</tr>";
+        }
+        html += "</table>";
+        html
+    }
+
+    #[test]
+    fn test_scrape_timetable_page() {
+        // Period 1 every day, and period 4 which is shown before lunch on Wednesday
+        let table = week_table(|row, _| row == 1 || row == 7);
+        let timetable = scrape_timetable_page(
+            &format!("{table}{table}"),
+            &LayoutProfile::default(),
+            &LessonRules::default(),
+        )
+        .unwrap();
+        assert_eq!(timetable.weeks.len(), 2);
+
+        let monday = &timetable.weeks[0].days[&DayOfWeek::Monday];
+        assert_eq!(monday.lessons().count(), 12);
+        assert_eq!(monday.get(Period::P0), Some(&Lesson::FreePeriod));
+        assert!(matches!(
+            monday.get(Period::P1),
+            Some(Lesson::Present { .. })
+        ));
+        assert_eq!(monday.get(Period::Recess), Some(&Lesson::Recess));
+        assert!(matches!(
+            monday.get(Period::P4),
+            Some(Lesson::Present { .. })
+        ));
+        assert_eq!(monday.get(Period::Lunch1), Some(&Lesson::Lunch1));
+        assert_eq!(monday.get(Period::Lunch2), Some(&Lesson::Lunch2));
+        assert_eq!(monday.get(Period::P5), Some(&Lesson::FreePeriod));
+        assert_eq!(monday.get(Period::Assembly), None);
+
+        let wednesday = &timetable.weeks[1].days[&DayOfWeek::Wednesday];
+        assert_eq!(wednesday.lessons().count(), 12);
+        assert_eq!(wednesday.get(Period::Assembly), Some(&Lesson::FreePeriod));
+        assert_eq!(wednesday.get(Period::Recess), Some(&Lesson::Recess));
+        assert_eq!(wednesday.get(Period::Lunch1), Some(&Lesson::Lunch1));
+        assert_eq!(wednesday.get(Period::Lunch2), Some(&Lesson::Lunch2));
+        assert!(matches!(
+            wednesday.get(Period::P4),
+            Some(Lesson::Present { .. })
+        ));
+        assert_eq!(wednesday.get(Period::P8), None);
+    }
+
+    #[test]
+    fn test_special_lessons() {
+        let scrape = |rules: &LessonRules, subject: &str, lesson_code: &str| {
+            let html = format!(
+                "<table><tr><td><span>1</span><strong>{subject}</strong>\
+                 <span>Smith J</span><span>~</span><small>{lesson_code}</small></td></tr></table>"
+            );
+            let document = Document::from(html.as_str());
+            let cell = document.find(Name("td")).next().unwrap();
+            match scrape_lesson(cell, rules).unwrap() {
+                Lesson::Present {
+                    subject,
+                    subject_code,
+                    ..
+                } => (subject, subject_code),
+                lesson => panic!("expected a class, got {lesson:?}"),
+            }
+        };
+        let subject = |name: &str, faculty: &str, kind| Subject {
+            name: name.to_string(),
+            faculty: faculty.to_string(),
+            kind,
+        };
+
+        let rules = LessonRules::default();
+        assert_eq!(
+            scrape(&rules, "HSIE | Commerce Yr9", "COM 1"),
+            (
+                subject("Commerce", "HSIE", LessonKind::Regular),
+                "COM".to_string()
+            )
+        );
+        assert_eq!(
+            scrape(&rules, "Pastoral Care Past_Car", "PC 1"),
+            (
+                subject("Assembly", "Pastoral Care", LessonKind::Assembly),
+                "PC".to_string()
+            )
+        );
+        assert_eq!(
+            scrape(&rules, "PDHPE | Tennis Yr9", "SPTTennis 1"),
+            (
+                subject("Tennis", "PDHPE", LessonKind::Sport),
+                "Tennis".to_string()
+            )
+        );
+        assert_eq!(
+            scrape(&rules, "Sport Sport", "SPTSport 1"),
+            (
+                subject("Sport", "Phys.Ed", LessonKind::Sport),
+                "Sport".to_string()
+            )
+        );
+
+        #[derive(serde::Deserialize)]
+        struct Config {
+            special_lessons: LessonRules,
+        }
+        let Config { special_lessons } = toml::from_str(
+            r#"
+            [[special_lessons]]
+            subject_code = "PC"
+            faculty = "Welfare"
+            kind = "pastoral"
+            "#,
+        )
+        .unwrap();
+        special_lessons.validate().unwrap();
+        // Added to the built in rules, so it's still named Assembly
+        assert_eq!(
+            scrape(&special_lessons, "Pastoral Care Past_Car", "PC 1"),
+            (
+                subject("Assembly", "Welfare", LessonKind::Pastoral),
+                "PC".to_string()
+            )
+        );
+
+        let Config { special_lessons } =
+            toml::from_str("[[special_lessons]]\nkind = \"sport\"").unwrap();
+        assert!(special_lessons.validate().is_err());
+    }
+
+    #[test]
+    fn test_scrape_error_location() {
+        let good = week_table(|row, _| row == 1);
+        // Thursday has a lesson in the Break/PC row
+        let bad = week_table(|row, column| row == 1 || (row == 4 && column == 3));
+
+        let error = scrape_timetable_page(
+            &format!("{good}{bad}"),
+            &LayoutProfile::default(),
+            &LessonRules::default(),
+        )
+        .unwrap_err();
+        assert!(matches!(error.kind, ScrapeErrorKind::ExpectedEmpty));
+        assert_eq!(error.week, Some(1));
+        assert_eq!(error.day, Some(DayOfWeek::Thursday));
+        assert_eq!(error.row, Some(4));
+        assert!(error.html.as_ref().unwrap().contains("COM 1"));
+    }
+}
@@ -0,0 +1,134 @@
+use crate::types::{week_name, DayOfWeek, MAX_WEEKS};
+use select::node::Node;
+use std::fmt;
+use std::ops::Deref;
+
+/// Longest HTML snippet kept in an error, in characters
+const MAX_HTML_LEN: usize = 300;
+
+/// Something on a timetable page didn't look the way the scraper expects,
+/// along with where it was found
+#[derive(Debug)]
+pub(crate) struct ScrapeError(Box<ScrapeErrorDetails>);
+
+#[derive(Debug)]
+pub(crate) struct ScrapeErrorDetails {
+    pub(crate) kind: ScrapeErrorKind,
+    pub(crate) student_id: Option<String>,
+    /// Index of the week table, 0 being week A
+    pub(crate) week: Option<usize>,
+    pub(crate) day: Option<DayOfWeek>,
+    /// Row of the week table, counting from 0
+    pub(crate) row: Option<usize>,
+    pub(crate) html: Option<String>,
+}
+
+#[derive(Debug)]
+pub(crate) enum ScrapeErrorKind {
+    MissingElement(&'static str),
+    Malformed { what: &'static str, text: String },
+    ExpectedEmpty,
+    RowCount { expected: usize, found: usize },
+    WeekCount(usize),
+}
+
+impl ScrapeError {
+    pub(crate) fn new(kind: ScrapeErrorKind, node: Node<'_>) -> Self {
+        let html = node.html();
+        let html = match html.char_indices().nth(MAX_HTML_LEN) {
+            Some((idx, _)) => format!("{}...", &html[..idx]),
+            None => html,
+        };
+
+        Self(Box::new(ScrapeErrorDetails {
+            kind,
+            student_id: None,
+            week: None,
+            day: None,
+            row: None,
+            html: Some(html),
+        }))
+    }
+
+    pub(crate) fn without_html(kind: ScrapeErrorKind) -> Self {
+        Self(Box::new(ScrapeErrorDetails {
+            kind,
+            student_id: None,
+            week: None,
+            day: None,
+            row: None,
+            html: None,
+        }))
+    }
+
+    pub(crate) fn with_student(mut self, student_id: &str) -> Self {
+        self.0
+            .student_id
+            .get_or_insert_with(|| student_id.to_string());
+        self
+    }
+
+    pub(crate) fn with_week(mut self, week: usize) -> Self {
+        self.0.week.get_or_insert(week);
+        self
+    }
+
+    pub(crate) fn with_day(mut self, day: DayOfWeek) -> Self {
+        self.0.day.get_or_insert(day);
+        self
+    }
+
+    pub(crate) fn with_row(mut self, row: usize) -> Self {
+        self.0.row.get_or_insert(row);
+        self
+    }
+}
+
+impl Deref for ScrapeError {
+    type Target = ScrapeErrorDetails;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl fmt::Display for ScrapeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut location = Vec::new();
+        if let Some(student_id) = &self.student_id {
+            location.push(format!("student {student_id}"));
+        }
+        if let Some(week) = self.week {
+            location.push(format!("week {}", week_name(week)));
+        }
+        if let Some(day) = self.day {
+            location.push(format!("{day:?}"));
+        }
+        if let Some(row) = self.row {
+            location.push(format!("row {row}"));
+        }
+        if !location.is_empty() {
+            write!(f, "{}: ", location.join(", "))?;
+        }
+
+        match &self.kind {
+            ScrapeErrorKind::MissingElement(element) => write!(f, "missing {element}")?,
+            ScrapeErrorKind::Malformed { what, text } => write!(f, "malformed {what} {text:?}")?,
+            ScrapeErrorKind::ExpectedEmpty => write!(f, "expected an empty cell")?,
+            ScrapeErrorKind::RowCount { expected, found } => {
+                write!(f, "expected {expected} rows, found {found}")?
+            }
+            ScrapeErrorKind::WeekCount(found) => {
+                write!(f, "found {found} weeks, but there can only be {MAX_WEEKS}")?
+            }
+        }
+
+        if let Some(html) = &self.html {
+            write!(f, "\n    {html}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for ScrapeError {}
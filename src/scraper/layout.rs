@@ -0,0 +1,212 @@
+use crate::generator::bell_times::BellTimes;
+use crate::types::{DayOfWeek, Period};
+use serde::Deserialize;
+use std::path::Path;
+
+/// Describes how the rows of a week table on the school's website map to periods.
+///
+/// Each day lists its periods in the order they actually happen, along with the table row
+/// they're read from. Rows which aren't listed are structural and must always be empty.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct LayoutProfile {
+    /// Number of rows in each week table
+    pub(crate) rows: usize,
+    /// Used for every day without its own list
+    default: Option<Vec<Slot>>,
+    monday: Option<Vec<Slot>>,
+    tuesday: Option<Vec<Slot>>,
+    wednesday: Option<Vec<Slot>>,
+    thursday: Option<Vec<Slot>>,
+    friday: Option<Vec<Slot>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Slot {
+    pub(crate) period: Period,
+    pub(crate) row: usize,
+    #[serde(default)]
+    pub(crate) kind: SlotKind,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SlotKind {
+    #[default]
+    Class,
+    /// Always empty on the website
+    Recess,
+    /// Empty unless a student has a class during lunch
+    Lunch1,
+    Lunch2,
+}
+
+impl LayoutProfile {
+    /// Loads a layout profile from a TOML file
+    pub(crate) fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("failed to read {}: {}", path.display(), e))?;
+        let profile: Self = toml::from_str(&text)
+            .map_err(|e| anyhow::anyhow!("failed to parse {}: {}", path.display(), e))?;
+
+        for day_of_week in DayOfWeek::iter().take(5) {
+            profile.validate_day(day_of_week).map_err(|e| {
+                anyhow::anyhow!(
+                    "invalid layout for {:?} in {}: {}",
+                    day_of_week,
+                    path.display(),
+                    e
+                )
+            })?;
+        }
+
+        Ok(profile)
+    }
+
+    /// Periods of a day in the order they happen
+    pub(crate) fn day(&self, day_of_week: DayOfWeek) -> &[Slot] {
+        let slots = match day_of_week {
+            DayOfWeek::Monday => &self.monday,
+            DayOfWeek::Tuesday => &self.tuesday,
+            DayOfWeek::Wednesday => &self.wednesday,
+            DayOfWeek::Thursday => &self.thursday,
+            DayOfWeek::Friday => &self.friday,
+        };
+
+        slots
+            .as_deref()
+            .or(self.default.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// Checks every day has the same periods in the same order as the bell times
+    pub(crate) fn check_against(&self, bell_times: &BellTimes) -> anyhow::Result<()> {
+        for day_of_week in DayOfWeek::iter().take(5) {
+            let layout_periods = self.day(day_of_week).iter().map(|slot| slot.period);
+            let bell_periods = bell_times
+                .day(day_of_week)
+                .bell_times
+                .iter()
+                .map(|bell_time| bell_time.period());
+
+            if !layout_periods.clone().eq(bell_periods.clone()) {
+                anyhow::bail!(
+                    "layout and bell times disagree on {:?}: layout has {}, bell times have {}",
+                    day_of_week,
+                    layout_periods
+                        .map(|period| period.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    bell_periods
+                        .map(|period| period.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate_day(&self, day_of_week: DayOfWeek) -> anyhow::Result<()> {
+        let slots = self.day(day_of_week);
+        anyhow::ensure!(!slots.is_empty(), "no periods");
+
+        for (idx, slot) in slots.iter().enumerate() {
+            anyhow::ensure!(
+                slot.row < self.rows,
+                "{} is in row {} but the table only has {} rows",
+                slot.period,
+                slot.row,
+                self.rows
+            );
+
+            if let Some(other) = slots[..idx].iter().find(|other| other.row == slot.row) {
+                anyhow::bail!(
+                    "{} and {} are both in row {}",
+                    other.period,
+                    slot.period,
+                    slot.row
+                );
+            }
+            if slots[..idx].iter().any(|other| other.period == slot.period) {
+                anyhow::bail!("{} is listed more than once", slot.period);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for LayoutProfile {
+    fn default() -> Self {
+        // The website shows every day with the same rows:
+        // 0, 1, 2, Assembly, Break/PC, R, 3, 4, L1, L2, 5, 6, 7, 8
+        let slot = |period, row, kind| Slot { period, row, kind };
+
+        // Break/PC (4) is always empty and there is only assembly (3) on Wednesday
+        let not_wednesday = vec![
+            slot(Period::P0, 0, SlotKind::Class),
+            slot(Period::P1, 1, SlotKind::Class),
+            slot(Period::P2, 2, SlotKind::Class),
+            slot(Period::Recess, 5, SlotKind::Recess),
+            slot(Period::P3, 6, SlotKind::Class),
+            slot(Period::P4, 7, SlotKind::Class),
+            slot(Period::Lunch1, 8, SlotKind::Lunch1),
+            slot(Period::Lunch2, 9, SlotKind::Lunch2),
+            slot(Period::P5, 10, SlotKind::Class),
+            slot(Period::P6, 11, SlotKind::Class),
+            slot(Period::P7, 12, SlotKind::Class),
+            slot(Period::P8, 13, SlotKind::Class),
+        ];
+
+        // The website shows period 4 on a Wednesday before lunch,
+        // when it actually happens after, and there is no period 8
+        let wednesday = vec![
+            slot(Period::P0, 0, SlotKind::Class),
+            slot(Period::P1, 1, SlotKind::Class),
+            slot(Period::P2, 2, SlotKind::Class),
+            slot(Period::Assembly, 3, SlotKind::Class),
+            slot(Period::Recess, 5, SlotKind::Recess),
+            slot(Period::P3, 6, SlotKind::Class),
+            slot(Period::Lunch1, 8, SlotKind::Lunch1),
+            slot(Period::Lunch2, 9, SlotKind::Lunch2),
+            slot(Period::P4, 7, SlotKind::Class),
+            slot(Period::P5, 10, SlotKind::Class),
+            slot(Period::P6, 11, SlotKind::Class),
+            slot(Period::P7, 12, SlotKind::Class),
+        ];
+
+        Self {
+            rows: 14,
+            default: Some(not_wednesday),
+            monday: None,
+            tuesday: None,
+            wednesday: Some(wednesday),
+            thursday: None,
+            friday: None,
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_example_matches_default() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("layout.example.toml");
+    let example = LayoutProfile::load(&path).unwrap();
+    let default = LayoutProfile::default();
+
+    assert_eq!(example.rows, default.rows);
+    for day_of_week in DayOfWeek::iter().take(5) {
+        assert_eq!(example.day(day_of_week), default.day(day_of_week));
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_default_matches_bell_times() {
+    LayoutProfile::default()
+        .check_against(&BellTimes::default())
+        .unwrap();
+}
@@ -0,0 +1,395 @@
+use crate::generator::template::Template;
+use crate::types::{Lesson, LessonKind, Subject};
+use serde::Deserialize;
+
+/// `[[special_lessons]]` in the config file, for lessons the website shows differently
+/// to regular classes
+///
+/// ```toml
+/// [[special_lessons]]
+/// subject = "Pastoral Care Past_Car"
+/// name = "Assembly"
+/// faculty = "Pastoral Care"
+/// kind = "assembly"
+///
+/// [[special_lessons]]
+/// code_prefix = "SPT"
+/// kind = "sport"
+/// ```
+///
+/// Every rule matching a lesson is applied in order, so later ones win.
+/// Rules from the config file come after the built in ones.
+///
+/// The name, faculty and kind are saved when scraping. Titles and colours are worked out
+/// when generating, from the kind and then any rule giving the lesson's name, faculty, kind
+/// or subject code, so changing them doesn't need another scrape.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(from = "Vec<LessonRule>")]
+pub(crate) struct LessonRules(Vec<LessonRule>);
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct LessonRule {
+    /// Subject text as shown on the website, i.e. "Sport Sport"
+    subject: Option<String>,
+    /// Subject code as shown on the website
+    subject_code: Option<String>,
+    /// Start of the subject code, which is taken off, i.e. "SPT" for "SPTTennis 1"
+    code_prefix: Option<String>,
+    /// Subject name to use instead of the one on the website
+    name: Option<String>,
+    faculty: Option<String>,
+    kind: Option<LessonKind>,
+    /// Title template used instead of the kind's
+    title: Option<Template>,
+    /// Colour used instead of the kind's
+    colour: Option<LessonColour>,
+}
+
+/// What the rules matching a lesson say about it when scraping
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Resolved<'a> {
+    pub(crate) name: Option<&'a str>,
+    pub(crate) faculty: Option<&'a str>,
+    pub(crate) kind: LessonKind,
+    /// With any prefixes taken off
+    pub(crate) subject_code: &'a str,
+}
+
+/// How a lesson's title and colour are shown when generating
+#[derive(Debug, PartialEq)]
+pub(crate) struct Treatment {
+    /// Used instead of the configured title template
+    pub(crate) title: Option<Template>,
+    pub(crate) colour: LessonColour,
+}
+
+/// Where the colour of a lesson comes from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum LessonColour {
+    /// A colour of its own, from the colour registry
+    #[default]
+    Subject,
+    /// The grey used for breaks and free periods
+    Neutral,
+}
+
+impl Default for LessonRules {
+    fn default() -> Self {
+        Self(vec![
+            LessonRule {
+                subject: Some("Pastoral Care Past_Car".to_string()),
+                name: Some("Assembly".to_string()),
+                faculty: Some("Pastoral Care".to_string()),
+                kind: Some(LessonKind::Assembly),
+                ..LessonRule::default()
+            },
+            LessonRule {
+                code_prefix: Some("SPT".to_string()),
+                kind: Some(LessonKind::Sport),
+                ..LessonRule::default()
+            },
+            LessonRule {
+                subject: Some("Sport Sport".to_string()),
+                name: Some("Sport".to_string()),
+                faculty: Some("Phys.Ed".to_string()),
+                kind: Some(LessonKind::Sport),
+                ..LessonRule::default()
+            },
+        ])
+    }
+}
+
+impl From<Vec<LessonRule>> for LessonRules {
+    fn from(rules: Vec<LessonRule>) -> Self {
+        let mut all = Self::default();
+        all.0.extend(rules);
+        all
+    }
+}
+
+impl LessonRules {
+    /// Fails if a rule doesn't say what it matches, since it would match every lesson,
+    /// or gives a title or colour without a way to find its lessons when generating
+    pub(crate) fn validate(&self) -> anyhow::Result<()> {
+        // Numbered as they are in the config file, after the built in ones
+        let built_in = Self::default().0.len();
+        for (idx, rule) in self.0.iter().enumerate().skip(built_in) {
+            anyhow::ensure!(
+                rule.subject.is_some() || rule.subject_code.is_some() || rule.code_prefix.is_some(),
+                "special lesson rule {} needs a subject, subject_code or code_prefix to match",
+                idx + 1 - built_in
+            );
+            anyhow::ensure!(
+                rule.title.is_none() && rule.colour.is_none() || rule.identifies(),
+                "special lesson rule {} gives a title or colour, so it needs a name, faculty, kind \
+                 or subject_code without a code_prefix to find its lessons when generating",
+                idx + 1 - built_in
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Applies every rule matching a lesson's subject text and code from the website
+    pub(crate) fn resolve<'a>(&'a self, subject: &str, subject_code: &'a str) -> Resolved<'a> {
+        let mut resolved = Resolved {
+            subject_code,
+            ..Resolved::default()
+        };
+
+        for rule in self
+            .0
+            .iter()
+            .filter(|rule| rule.matches(subject, subject_code))
+        {
+            if let Some(prefix) = &rule.code_prefix {
+                resolved.subject_code = resolved
+                    .subject_code
+                    .strip_prefix(prefix.as_str())
+                    .unwrap_or(resolved.subject_code);
+            }
+            resolved.name = rule.name.as_deref().or(resolved.name);
+            resolved.faculty = rule.faculty.as_deref().or(resolved.faculty);
+            resolved.kind = rule.kind.unwrap_or(resolved.kind);
+        }
+
+        resolved
+    }
+
+    /// Works out the title and colour of a scraped lesson from its kind,
+    /// then every rule giving its name, faculty, kind or subject code
+    pub(crate) fn treatment(&self, subject: &Subject, subject_code: &str) -> Treatment {
+        let mut treatment = Treatment::of_kind(subject.kind);
+
+        for rule in self
+            .0
+            .iter()
+            .filter(|rule| rule.gives(subject, subject_code))
+        {
+            if let Some(title) = &rule.title {
+                treatment.title = Some(title.clone());
+            }
+            treatment.colour = rule.colour.unwrap_or(treatment.colour);
+        }
+
+        treatment
+    }
+
+    /// Undoes the title a rule or its kind gives, for reading titles back out of
+    /// `.timetable` files. The last rule giving a title which can be undone wins,
+    /// like when generating. Fails if the title doesn't say which subject it is.
+    pub(crate) fn unrender_title(
+        &self,
+        title: &str,
+        room: Option<&str>,
+    ) -> anyhow::Result<Option<Lesson>> {
+        let lesson = self.0.iter().enumerate().rev().find_map(|(idx, rule)| {
+            let template = rule
+                .title
+                .clone()
+                .or_else(|| Treatment::of_kind(rule.kind?).title)?;
+            let values = template.unrender(title)?;
+
+            // Rules before it giving the same kind fill in what it doesn't give
+            let rules = std::iter::once(rule).chain(
+                self.0[..idx]
+                    .iter()
+                    .rev()
+                    .filter(|other| rule.kind.is_some() && other.kind == rule.kind),
+            );
+            let give = |field: fn(&LessonRule) -> Option<&str>| {
+                rules.clone().find_map(field).map(str::to_string)
+            };
+
+            // Classes with only a name are found by it when importing
+            let subject_code = values
+                .subject_code
+                .or_else(|| give(LessonRule::kept_subject_code));
+            let Some(name) = values
+                .subject
+                .or_else(|| give(|rule| rule.name.as_deref()))
+                .or_else(|| subject_code.clone())
+            else {
+                return Some(Err(anyhow::anyhow!(
+                    "{:?} has the title of a special lesson rule without a subject code or name",
+                    title
+                )));
+            };
+
+            Some(Ok(Lesson::Present {
+                subject: Subject {
+                    name,
+                    faculty: values
+                        .faculty
+                        .or_else(|| give(|rule| rule.faculty.as_deref()))
+                        .unwrap_or_default(),
+                    kind: rule.kind.unwrap_or_default(),
+                },
+                teacher: None,
+                room: values.room.or(room.map(str::to_string)),
+                subject_code: subject_code.unwrap_or_default(),
+                class_code: values.class_code.unwrap_or_default(),
+            }))
+        });
+
+        lesson.transpose()
+    }
+}
+
+impl Treatment {
+    fn of_kind(kind: LessonKind) -> Self {
+        match kind {
+            LessonKind::Assembly => Self {
+                title: Some("Assembly {subject_code}.{class_code}".parse().unwrap()),
+                colour: LessonColour::Neutral,
+            },
+            LessonKind::Regular | LessonKind::Sport | LessonKind::Pastoral => Self {
+                title: None,
+                colour: LessonColour::Subject,
+            },
+        }
+    }
+}
+
+impl LessonRule {
+    /// Everything the rule gives has to match
+    fn matches(&self, subject: &str, subject_code: &str) -> bool {
+        self.subject.as_ref().is_none_or(|text| text == subject)
+            && self
+                .subject_code
+                .as_ref()
+                .is_none_or(|code| code == subject_code)
+            && self
+                .code_prefix
+                .as_ref()
+                .is_none_or(|prefix| subject_code.starts_with(prefix.as_str()))
+    }
+
+    /// Subject code a scraped lesson keeps, which isn't known once a prefix is taken off
+    fn kept_subject_code(&self) -> Option<&str> {
+        self.subject_code
+            .as_deref()
+            .filter(|_| self.code_prefix.is_none())
+    }
+
+    /// Whether the rule can find the lessons it was applied to once they're scraped
+    fn identifies(&self) -> bool {
+        self.name.is_some()
+            || self.faculty.is_some()
+            || self.kind.is_some()
+            || self.kept_subject_code().is_some()
+    }
+
+    /// Whether a scraped lesson has the name, faculty, kind and subject code the rule gives it
+    fn gives(&self, subject: &Subject, subject_code: &str) -> bool {
+        self.identifies()
+            && self.name.as_ref().is_none_or(|name| *name == subject.name)
+            && self
+                .faculty
+                .as_ref()
+                .is_none_or(|faculty| *faculty == subject.faculty)
+            && self.kind.is_none_or(|kind| kind == subject.kind)
+            && self
+                .kept_subject_code()
+                .is_none_or(|code| code == subject_code)
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn test_treatment() {
+    #[derive(Deserialize)]
+    struct Config {
+        special_lessons: LessonRules,
+    }
+    let Config { special_lessons } = toml::from_str(
+        r#"
+        [[special_lessons]]
+        subject_code = "CHAP"
+        name = "Chapel"
+        title = "Chapel {class_code}"
+        colour = "neutral"
+
+        [[special_lessons]]
+        subject = "Pastoral Care Past_Car"
+        kind = "assembly"
+        colour = "subject"
+        "#,
+    )
+    .unwrap();
+    special_lessons.validate().unwrap();
+
+    let treatment = |name: &str, kind, subject_code: &str| {
+        let subject = Subject {
+            name: name.to_string(),
+            faculty: String::new(),
+            kind,
+        };
+        let treatment = special_lessons.treatment(&subject, subject_code);
+        (
+            treatment.title.map(|title| title.to_string()),
+            treatment.colour,
+        )
+    };
+
+    assert_eq!(
+        treatment("Commerce", LessonKind::Regular, "COM"),
+        (None, LessonColour::Subject)
+    );
+    // Assemblies get their title from their kind, and their colour from the rule
+    assert_eq!(
+        treatment("Assembly", LessonKind::Assembly, "PC"),
+        (
+            Some("Assembly {subject_code}.{class_code}".to_string()),
+            LessonColour::Subject
+        )
+    );
+    assert_eq!(
+        treatment("Chapel", LessonKind::Regular, "CHAP"),
+        (
+            Some("Chapel {class_code}".to_string()),
+            LessonColour::Neutral
+        )
+    );
+    // Only chapel was given its name by the rule
+    assert_eq!(
+        treatment("Chaplaincy", LessonKind::Regular, "CHAP"),
+        (None, LessonColour::Subject)
+    );
+
+    // Titles are read back as the lessons the rules give them to
+    assert_eq!(
+        special_lessons
+            .unrender_title("Assembly PC.1", None)
+            .unwrap(),
+        Some(Lesson::assembly())
+    );
+    assert_eq!(
+        special_lessons.unrender_title("Chapel 2", None).unwrap(),
+        Some(Lesson::Present {
+            subject: Subject {
+                name: "Chapel".to_string(),
+                faculty: String::new(),
+                kind: LessonKind::Regular,
+            },
+            teacher: None,
+            room: None,
+            subject_code: "CHAP".to_string(),
+            class_code: "2".to_string(),
+        })
+    );
+    assert_eq!(special_lessons.unrender_title("COM.1", None).unwrap(), None);
+
+    // Nothing about the lesson is left to find it by when generating
+    let Config { special_lessons } = toml::from_str(
+        r#"
+        [[special_lessons]]
+        subject = "Chapel Chapel"
+        colour = "neutral"
+        "#,
+    )
+    .unwrap();
+    assert!(special_lessons.validate().is_err());
+}
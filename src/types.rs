@@ -1,29 +1,117 @@
+use chrono::Weekday;
+use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
+use std::fmt;
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub(crate) struct StudentIdentity {
     pub(crate) first: String,
     pub(crate) last: String,
     pub(crate) id: String,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub(crate) struct Timetable {
-    pub(crate) week_a: Week,
-    pub(crate) week_b: Week,
+    /// Every week of the cycle in order, starting with week A
+    pub(crate) weeks: Vec<Week>,
 }
 
-#[derive(Debug, PartialEq, Default)]
+/// Weeks are known by a letter, so a cycle can't be any longer than this
+pub(crate) const MAX_WEEKS: usize = 26;
+
+/// Letter a week is known by, i.e. 0 is week A.
+/// Only weeks below [`MAX_WEEKS`] have one.
+pub(crate) fn week_name(week: usize) -> char {
+    (b'A' + week as u8) as char
+}
+
+/// Turns a week letter into its index in the cycle
+pub(crate) fn parse_week_name(text: &str) -> Result<usize, String> {
+    match text.as_bytes() {
+        [letter @ b'A'..=b'Z'] => Ok((letter - b'A') as usize),
+        [letter @ b'a'..=b'z'] => Ok((letter - b'a') as usize),
+        _ => Err(format!("{text:?} is not a week letter")),
+    }
+}
+
+#[derive(Debug, PartialEq, Default, Serialize, Deserialize)]
 pub(crate) struct Week {
     pub(crate) days: BTreeMap<DayOfWeek, Day>,
 }
 
-#[derive(Debug, PartialEq, Default)]
+/// Lessons keyed by the period they're in.
+/// The order periods happen in comes from the bell times, not from here.
+#[derive(Debug, PartialEq, Default, Serialize, Deserialize)]
 pub(crate) struct Day {
-    pub(crate) lessons: Vec<Lesson>,
+    lessons: BTreeMap<Period, Lesson>,
+}
+
+impl Day {
+    /// Fails if a period is given more than once
+    pub(crate) fn from_lessons(
+        lessons: impl IntoIterator<Item = (Period, Lesson)>,
+    ) -> Result<Self, Period> {
+        let mut day = Self::default();
+
+        for (period, lesson) in lessons {
+            if day.lessons.insert(period, lesson).is_some() {
+                return Err(period);
+            }
+        }
+
+        Ok(day)
+    }
+
+    pub(crate) fn get(&self, period: Period) -> Option<&Lesson> {
+        self.lessons.get(&period)
+    }
+
+    pub(crate) fn lessons(&self) -> impl Iterator<Item = (Period, &Lesson)> {
+        self.lessons
+            .iter()
+            .map(|(period, lesson)| (*period, lesson))
+    }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub(crate) enum Period {
+    P0,
+    P1,
+    P2,
+    P3,
+    P4,
+    P5,
+    P6,
+    P7,
+    P8,
+    Assembly,
+    Recess,
+    Lunch1,
+    Lunch2,
+}
+
+impl fmt::Display for Period {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Period::P0 => "P0",
+            Period::P1 => "P1",
+            Period::P2 => "P2",
+            Period::P3 => "P3",
+            Period::P4 => "P4",
+            Period::P5 => "P5",
+            Period::P6 => "P6",
+            Period::P7 => "P7",
+            Period::P8 => "P8",
+            Period::Assembly => "Assembly",
+            Period::Recess => "Recess",
+            Period::Lunch1 => "Lunch1",
+            Period::Lunch2 => "Lunch2",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
 pub(crate) enum Lesson {
     Present {
         subject: Subject,
@@ -33,20 +121,108 @@ pub(crate) enum Lesson {
         class_code: String,
     },
     FreePeriod,
-    /// Free period before the first class or after the last class
+    /// Free period before the first class or after the last class,
+    /// from timetables scraped before free periods were kept as they are
     AbsentPeriod,
     Recess,
     Lunch1,
     Lunch2,
 }
 
-#[derive(Debug, PartialEq, Eq, Ord, PartialOrd)]
+impl Lesson {
+    /// Free periods are shown depending on where they are in the day,
+    /// so absent periods are the same as them
+    pub(crate) fn is_free(&self) -> bool {
+        matches!(self, Lesson::FreePeriod | Lesson::AbsentPeriod)
+    }
+}
+
+/// Builds classes for tests, starting from what importing a `.timetable` file gives back
+#[cfg(test)]
+impl Lesson {
+    /// Class 1 of a subject named after its code, without a faculty, teacher or room
+    pub(crate) fn class(subject_code: &str) -> Self {
+        Lesson::Present {
+            subject: Subject {
+                name: subject_code.to_string(),
+                faculty: String::new(),
+                kind: LessonKind::Regular,
+            },
+            teacher: None,
+            room: None,
+            subject_code: subject_code.to_string(),
+            class_code: "1".to_string(),
+        }
+    }
+
+    pub(crate) fn named(mut self, name: &str) -> Self {
+        if let Lesson::Present { subject, .. } = &mut self {
+            subject.name = name.to_string();
+        }
+        self
+    }
+
+    pub(crate) fn in_faculty(mut self, faculty: &str) -> Self {
+        if let Lesson::Present { subject, .. } = &mut self {
+            subject.faculty = faculty.to_string();
+        }
+        self
+    }
+
+    /// Pastoral care as the built in special lesson rules give it
+    pub(crate) fn assembly() -> Self {
+        let mut lesson = Lesson::class("PC")
+            .named("Assembly")
+            .in_faculty("Pastoral Care");
+        if let Lesson::Present { subject, .. } = &mut lesson {
+            subject.kind = LessonKind::Assembly;
+        }
+        lesson
+    }
+
+    pub(crate) fn in_room(mut self, room: &str) -> Self {
+        if let Lesson::Present {
+            room: lesson_room, ..
+        } = &mut self
+        {
+            *lesson_room = Some(room.to_string());
+        }
+        self
+    }
+
+    pub(crate) fn taught_by(mut self, first_name: &str, last_name: &str) -> Self {
+        if let Lesson::Present { teacher, .. } = &mut self {
+            *teacher = Some(Teacher {
+                first_name: Some(first_name.to_string()),
+                last_name: last_name.to_string(),
+            });
+        }
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Ord, PartialOrd, Serialize, Deserialize)]
 pub(crate) struct Subject {
     pub(crate) name: String,
     pub(crate) faculty: String,
+    pub(crate) kind: LessonKind,
+}
+
+/// What sort of lesson a subject is, set by the special lesson rules when scraping
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Ord, PartialOrd, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum LessonKind {
+    #[default]
+    Regular,
+    /// Titled "Assembly" with the neutral colour unless a rule says otherwise
+    Assembly,
+    Sport,
+    Pastoral,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub(crate) struct Teacher {
     pub(crate) first_name: Option<String>,
     pub(crate) last_name: String,
@@ -67,7 +243,9 @@ impl Teacher {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, clap::ValueEnum,
+)]
 pub(crate) enum DayOfWeek {
     Monday,
     Tuesday,
@@ -83,6 +261,18 @@ impl DayOfWeek {
     pub(crate) fn iter() -> DayOfWeekIter {
         DayOfWeekIter(None)
     }
+
+    /// `None` on weekends
+    pub(crate) fn from_weekday(weekday: Weekday) -> Option<Self> {
+        match weekday {
+            Weekday::Mon => Some(DayOfWeek::Monday),
+            Weekday::Tue => Some(DayOfWeek::Tuesday),
+            Weekday::Wed => Some(DayOfWeek::Wednesday),
+            Weekday::Thu => Some(DayOfWeek::Thursday),
+            Weekday::Fri => Some(DayOfWeek::Friday),
+            Weekday::Sat | Weekday::Sun => None,
+        }
+    }
 }
 
 impl Iterator for DayOfWeekIter {